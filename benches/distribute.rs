@@ -0,0 +1,51 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Times `distribute` over large sections, where most accounts share a few work values.
+//! Run with `cargo bench`.
+
+use safe_farming::{RewardAlgo, StorageRewards};
+use safe_nd::{Money, PublicKey};
+use std::{collections::HashMap, time::Instant};
+use threshold_crypto::SecretKey;
+
+const ROUNDS: u32 = 20;
+
+fn main() {
+    let calc = StorageRewards::new(Money::zero());
+    for section_size in &[1_000, 10_000, 100_000] {
+        let accounts_work: HashMap<_, _> = (0..*section_size)
+            .map(|i| {
+                let id = PublicKey::from(SecretKey::random().public_key());
+                (id, [1, 2, 3, 5, 8][i % 5])
+            })
+            .collect();
+        let all_work = accounts_work.values().map(|work| *work as u128).sum();
+        let data_hash = [7; 32];
+        // An odd total, so that the shares need reconciling.
+        let total_reward = Money::from_nano(1_000_000_007);
+
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let _ = calc.distribute(total_reward, accounts_work.clone());
+        }
+        let plain = start.elapsed() / ROUNDS;
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let _ =
+                calc.distribute_for_data(total_reward, accounts_work.clone(), all_work, &data_hash);
+        }
+        let for_data = start.elapsed() / ROUNDS;
+
+        println!(
+            "{:>7} accounts: distribute {:?}, distribute_for_data {:?}",
+            section_size, plain, for_data
+        );
+    }
+}
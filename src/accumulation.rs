@@ -7,31 +7,298 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{AccountAdded, AccountId, AccumulationEvent, RewardsAccumulated, RewardsClaimed};
-use safe_nd::{Error, Money, Result, RewardCounter, Work};
-use std::collections::{HashMap, HashSet};
+use super::{
+    calculation::RewardAlgo,
+    error::{FarmingError, Result},
+    utils::work_from_age,
+    AccountAdded, AccountId, AccountRemoved, AccumulationEvent, NodeAge, RewardsAccumulated,
+    RewardsClaimed, RewardsClaimedAndTransferred, WorkUpdated,
+};
+use safe_nd::{Error, Money, RewardCounter, Work};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    fmt,
+    num::NonZeroUsize,
+    sync::Arc,
+};
 
 /// The book keeping of rewards.
 /// The business rule is that a piece of data
 /// is only rewarded once.
+/// When deserialized, the cached total work is recomputed, and with a cap,
+/// the ids are taken in sorted order, as by `new`.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "AccumulationSnapshot"))]
 pub struct Accumulation {
     idempotency: HashSet<Id>,
+    // The order the ids were rewarded in, oldest first, kept only when capped.
+    // Derived from the ids, so not trusted from a snapshot.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    idempotency_order: VecDeque<Id>,
+    idempotency_cap: Option<NonZeroUsize>,
     accumulated: HashMap<AccountId, RewardCounter>,
+    claimed: Money,
+    soft_cap: Option<Money>,
+    // Hooks are local to the instance, and not replicated.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    soft_cap_hook: Option<SoftCapHook>,
+    max_work: Option<Work>,
+    rewarded_bytes: Option<HashMap<Id, u64>>,
+    distributions: Option<HashMap<Id, HashMap<AccountId, Money>>>,
+    // Derived from the accounts, so not trusted from a snapshot.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    total_work: u128,
+}
+
+/// The serialized fields of an `Accumulation`, from which the derived ones are rebuilt.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct AccumulationSnapshot {
+    idempotency: HashSet<Id>,
+    idempotency_cap: Option<NonZeroUsize>,
+    accumulated: HashMap<AccountId, RewardCounter>,
+    claimed: Money,
+    soft_cap: Option<Money>,
+    max_work: Option<Work>,
+    rewarded_bytes: Option<HashMap<Id, u64>>,
+    distributions: Option<HashMap<Id, HashMap<AccountId, Money>>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<AccumulationSnapshot> for Accumulation {
+    fn from(snapshot: AccumulationSnapshot) -> Self {
+        let mut idempotency: Vec<Id> = snapshot.idempotency.into_iter().collect();
+        idempotency.sort();
+        let parts = AccumulationParts {
+            idempotency,
+            accumulated: snapshot.accumulated,
+            claimed: snapshot.claimed,
+            rewarded_bytes: snapshot.rewarded_bytes,
+            distributions: snapshot.distributions,
+        };
+        let mut acc = Self::from_parts(parts, snapshot.idempotency_cap);
+        acc.soft_cap = snapshot.soft_cap;
+        acc.max_work = snapshot.max_work;
+        acc
+    }
+}
+
+/// The state of an `Accumulation`, as taken apart by `Accumulation::into_parts`,
+/// for a snapshot that needs no serde, leaving the choice of format to the caller.
+/// The settings, i.e. the caps and the hook, are not part of it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AccumulationParts {
+    /// The rewarded ids, oldest first when the idempotency is capped,
+    /// and sorted otherwise.
+    pub idempotency: Vec<Id>,
+    /// The counters of the accounts.
+    pub accumulated: HashMap<AccountId, RewardCounter>,
+    /// See `Accumulation::claimed_total`.
+    pub claimed: Money,
+    /// The sizes of the rewarded data, if tracked.
+    pub rewarded_bytes: Option<HashMap<Id, u64>>,
+    /// The distributions of the rewarded data, if tracked.
+    pub distributions: Option<HashMap<Id, HashMap<AccountId, Money>>>,
 }
 
 /// Identification type
 pub type Id = Vec<u8>;
 
+/// Called with an account and its new accumulated reward,
+/// when an accumulation makes that reward cross the soft cap.
+pub type SoftCapHook = Arc<dyn Fn(&AccountId, Money) + Send + Sync>;
+
+/// How the supply totals of an `Accumulation`
+/// would look after a claim.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct SupplyImpact {
+    /// The amount that would be paid out.
+    pub amount: Money,
+    /// The `total_accumulated` after the claim.
+    pub total_accumulated: Money,
+    /// The `claimed_total` after the claim.
+    pub claimed_total: Money,
+}
+
+/// Why an accumulation was rejected by `Accumulation::accumulate_checked`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AccumulateError {
+    /// The reward of the account would overflow with its share, which is given.
+    /// Other accounts may overflow as well.
+    Overflow(AccountId, Money),
+    /// Any other reason.
+    Other(FarmingError),
+}
+
+impl From<AccumulateError> for FarmingError {
+    fn from(error: AccumulateError) -> Self {
+        match error {
+            AccumulateError::Overflow(_, _) => FarmingError::Overflow,
+            AccumulateError::Other(error) => error,
+        }
+    }
+}
+
+impl From<AccumulateError> for Error {
+    fn from(error: AccumulateError) -> Self {
+        FarmingError::from(error).into()
+    }
+}
+
+/// The outcome of `Accumulation::accumulate_or_recorded`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Accumulated {
+    /// The data had not been rewarded, and the event is to be applied.
+    New(RewardsAccumulated),
+    /// The data had already been rewarded, with the given distribution.
+    /// There is nothing to apply.
+    Recorded(HashMap<AccountId, Money>),
+}
+
+/// A claim, with its payout vested over a number of rounds,
+/// counted from the round of the claim.
+/// Nothing is unlocked before the cliff; after it, the total unlocks
+/// linearly over the vesting rounds, and is fully unlocked at the end of them.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct VestedClaim {
+    /// The claim to apply, which removes the account as usual.
+    pub claim: RewardsClaimed,
+    /// The number of rounds before anything is unlocked.
+    pub cliff_rounds: u64,
+    /// The number of rounds until the total is unlocked.
+    pub vest_rounds: u64,
+}
+
 impl Accumulation {
     /// ctor
-    pub fn new(idempotency: HashSet<Id>, accumulated: HashMap<AccountId, RewardCounter>) -> Self {
-        Self {
-            idempotency,
+    ///
+    /// With an `idempotency_cap`, only the ids of the most recently rewarded
+    /// `cap` pieces of data are kept, and the oldest are evicted beyond that.
+    /// This bounds the memory of a long-lived section, at the cost of an evicted
+    /// piece of data being rewardable again, should it ever be submitted anew.
+    /// What is tracked for an evicted id, such as its size, is evicted with it.
+    /// Initial ids are taken as older than any rewarded later, in sorted order.
+    /// With `None`, all ids are kept forever.
+    pub fn new(
+        idempotency: HashSet<Id>,
+        accumulated: HashMap<AccountId, RewardCounter>,
+        idempotency_cap: Option<NonZeroUsize>,
+    ) -> Self {
+        let total_work = accumulated.values().map(|c| c.work as u128).sum();
+        let mut acc = Self {
+            idempotency: Default::default(),
+            idempotency_order: Default::default(),
+            idempotency_cap,
             accumulated,
+            claimed: Money::zero(),
+            soft_cap: None,
+            soft_cap_hook: None,
+            max_work: None,
+            rewarded_bytes: None,
+            distributions: None,
+            total_work,
+        };
+        let mut ids: Vec<Id> = idempotency.into_iter().collect();
+        ids.sort();
+        acc.restore_idempotency(ids);
+        acc
+    }
+
+    /// Takes the instance apart, see `AccumulationParts`,
+    /// which `from_parts` puts back together.
+    pub fn into_parts(self) -> AccumulationParts {
+        let idempotency = if self.idempotency_cap.is_some() {
+            self.idempotency_order.into_iter().collect()
+        } else {
+            let mut ids: Vec<Id> = self.idempotency.into_iter().collect();
+            ids.sort();
+            ids
+        };
+        AccumulationParts {
+            idempotency,
+            accumulated: self.accumulated,
+            claimed: self.claimed,
+            rewarded_bytes: self.rewarded_bytes,
+            distributions: self.distributions,
+        }
+    }
+
+    /// Puts an instance back together from the parts taken by `into_parts`,
+    /// with the idempotency capped as by `new`. The sizes and distributions
+    /// are tracked if they were, and only kept for the ids that survive the cap.
+    pub fn from_parts(parts: AccumulationParts, idempotency_cap: Option<NonZeroUsize>) -> Self {
+        let mut acc = Self::new(Default::default(), parts.accumulated, idempotency_cap);
+        acc.restore_idempotency(parts.idempotency);
+        acc.claimed = parts.claimed;
+        let idempotency = &acc.idempotency;
+        acc.rewarded_bytes = parts.rewarded_bytes.map(|mut sizes| {
+            sizes.retain(|id, _| idempotency.contains(id));
+            sizes
+        });
+        acc.distributions = parts.distributions.map(|mut distributions| {
+            distributions.retain(|id, _| idempotency.contains(id));
+            distributions
+        });
+        acc
+    }
+
+    /// Rebuilds the state by applying the events in order to an empty instance,
+    /// so that an append-only log of events can be persisted instead of the state.
+    /// The idempotency is not capped.
+    pub fn from_events(events: impl IntoIterator<Item = AccumulationEvent>) -> Result<Self> {
+        let mut acc = Self::new(Default::default(), Default::default(), None);
+        for event in events {
+            acc.apply(event)?;
+        }
+        Ok(acc)
+    }
+
+    /// Opts in to recording the size of every rewarded piece of data,
+    /// as accumulated with `accumulate_sized`, so that the total
+    /// bytes rewarded can be reported without a separate store.
+    pub fn track_rewarded_bytes(&mut self) {
+        if self.rewarded_bytes.is_none() {
+            self.rewarded_bytes = Some(Default::default());
+        }
+    }
+
+    /// Opts in to retaining the distribution of every piece of data rewarded from now on,
+    /// so that a caller rejected with `FarmingError::AlreadyRewarded`,
+    /// e.g. when racing another Elder, can recover what was recorded,
+    /// with `get_accumulated_distribution`.
+    /// Off by default, since it keeps every distribution in memory.
+    /// With an idempotency cap, the distributions are evicted together with their ids.
+    pub fn track_distributions(&mut self) {
+        if self.distributions.is_none() {
+            self.distributions = Some(Default::default());
         }
     }
 
+    /// Caps the work an account can accumulate by being rewarded.
+    /// Without a cap, work grows by one per reward for as long as the account lives,
+    /// so the oldest accounts eventually dominate every distribution.
+    /// With a cap, accounts reaching it stop growing, which flattens the
+    /// distribution at the top: all capped accounts get equal shares,
+    /// while younger accounts keep catching up.
+    pub fn set_max_work(&mut self, max_work: Option<Work>) {
+        self.max_work = max_work;
+    }
+
+    /// Sets a soft ceiling for accumulated rewards, below the hard max of `Money`.
+    /// Accounts crossing it are still rewarded, but the soft cap hook is
+    /// called, as an early warning before they become unrewardable.
+    /// The hook is called by `apply`, so only once the rewards are accumulated.
+    pub fn set_soft_cap(&mut self, soft_cap: Option<Money>) {
+        self.soft_cap = soft_cap;
+    }
+
+    /// Sets the hook called when an account crosses the soft cap.
+    pub fn on_soft_cap_crossed(&mut self, hook: SoftCapHook) {
+        self.soft_cap_hook = Some(hook);
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Queries ----------------------------------
     /// -----------------------------------------------------------------
@@ -46,42 +313,452 @@ impl Accumulation {
         &self.accumulated
     }
 
+    /// Iterates over all accounts and their counters, in no particular order,
+    /// without depending on how they are stored.
+    pub fn iter(&self) -> impl Iterator<Item = (&AccountId, &RewardCounter)> {
+        self.accumulated.iter()
+    }
+
+    /// Iterates over all accounts, in no particular order.
+    pub fn accounts(&self) -> impl Iterator<Item = &AccountId> {
+        self.accumulated.keys()
+    }
+
+    /// Returns a read-only view of this instance.
+    pub fn view(&self) -> AccumulationView<'_> {
+        AccumulationView { accumulation: self }
+    }
+
+    /// Returns the sum of all rewards accumulated and not yet claimed,
+    /// saturating at the max value of `Money`.
+    pub fn total_accumulated(&self) -> Money {
+        let total = self.accumulated.values().fold(0_u64, |sum, counter| {
+            sum.saturating_add(counter.reward.as_nano())
+        });
+        Money::from_nano(total)
+    }
+
+    /// Returns the sum of all rewards claimed from this instance.
+    /// It is bookkeeping only, and saturates at the max value.
+    pub fn claimed_total(&self) -> Money {
+        self.claimed
+    }
+
+    /// Returns the sum of the work of all accounts, as kept up to date by `apply`,
+    /// so that it need not be summed up for every reward.
+    /// It is a u128 for the same reason as in `RewardAlgo::distribute`.
+    pub fn cached_total_work(&self) -> u128 {
+        self.total_work
+    }
+
+    /// Returns the sum of all rewards accumulated and not yet claimed.
+    /// Unlike `total_accumulated`, this does not saturate, but returns
+    /// `FarmingError::Overflow` if the sum overflows.
+    pub fn total_outstanding_reward(&self) -> Result<Money> {
+        self.accumulated
+            .values()
+            .try_fold(Money::zero(), |sum, counter| {
+                sum.checked_add(counter.reward)
+            })
+            .ok_or(FarmingError::Overflow)
+    }
+
+    /// Returns the sum of the work of all accounts,
+    /// or `FarmingError::Overflow` if it does not fit in a `Work`.
+    pub fn total_work(&self) -> Result<Work> {
+        Work::try_from(self.total_work).map_err(|_| FarmingError::Overflow)
+    }
+
+    /// Returns all reward issued by this instance, i.e. the sum of
+    /// `total_accumulated` and `claimed_total`, saturating at the max value.
+    pub fn issued_total(&self) -> Money {
+        let issued = self
+            .total_accumulated()
+            .as_nano()
+            .saturating_add(self.claimed.as_nano());
+        Money::from_nano(issued)
+    }
+
+    /// Returns the signed difference (in nanos) between the `issued_total`
+    /// of this instance and the total of an external ledger.
+    /// Positive if this instance has issued more, and zero if they match.
+    /// Returns `FarmingError::Overflow` if the difference does not fit an i64.
+    pub fn reconcile(&self, ledger_total: Money) -> Result<i64> {
+        let diff = self.issued_total().as_nano() as i128 - ledger_total.as_nano() as i128;
+        if diff > i64::MAX as i128 || diff < i64::MIN as i128 {
+            return Err(FarmingError::Overflow);
+        }
+        Ok(diff as i64)
+    }
+
+    /// Returns how much more reward can be issued, before the
+    /// `issued_total` reaches `max_supply`. Zero if it already has.
+    pub fn supply_headroom(&self, max_supply: Money) -> Money {
+        Money::from_nano(
+            max_supply
+                .as_nano()
+                .saturating_sub(self.issued_total().as_nano()),
+        )
+    }
+
+    /// Returns how the supply totals would change if the account claimed now,
+    /// without claiming anything. None if the account doesn't exist.
+    pub fn simulate_claim(&self, account: &AccountId) -> Option<SupplyImpact> {
+        let amount = self.accumulated.get(account)?.reward;
+        let total_accumulated =
+            Money::from_nano(self.total_accumulated().as_nano() - amount.as_nano());
+        let claimed_total =
+            Money::from_nano(self.claimed.as_nano().saturating_add(amount.as_nano()));
+        Some(SupplyImpact {
+            amount,
+            total_accumulated,
+            claimed_total,
+        })
+    }
+
+    /// Returns the accumulated reward (in nanos) per unit of work of the account.
+    /// None if the account doesn't exist, or has no work to relate the reward to.
+    pub fn reward_rate(&self, account: &AccountId) -> Option<f64> {
+        let counter = self.accumulated.get(account)?;
+        if counter.work == 0 {
+            return None;
+        }
+        Some(counter.reward.as_nano() as f64 / counter.work as f64)
+    }
+
+    /// Returns the average work of all accounts, weighted by their accumulated reward,
+    /// i.e. `sum(reward * work) / sum(reward)`. None if nothing has been accumulated.
+    pub fn reward_weighted_avg_work(&self) -> Option<f64> {
+        let (weighted, total) = self.accumulated.values().fold((0_u128, 0_u128), |sums, c| {
+            let reward = c.reward.as_nano() as u128;
+            (
+                sums.0.saturating_add(reward * c.work as u128),
+                sums.1 + reward,
+            )
+        });
+        if total == 0 {
+            return None;
+        }
+        Some(weighted as f64 / total as f64)
+    }
+
+    /// Returns the accumulated reward of the account as a fraction of the
+    /// `total_accumulated` of all accounts.
+    /// None if the account doesn't exist, or if nothing has been accumulated.
+    pub fn reward_fraction(&self, account: &AccountId) -> Option<f64> {
+        let reward = self.accumulated.get(account)?.reward;
+        let total = self.total_accumulated();
+        if total.as_nano() == 0 {
+            return None;
+        }
+        Some(reward.as_nano() as f64 / total.as_nano() as f64)
+    }
+
+    /// Returns the number of rewards the account needs to receive,
+    /// for its work to reach the median work of all accounts.
+    /// This relies on the current work policy, where work increases by one per reward,
+    /// and assumes the median itself stays put (i.e. the others are not rewarded meanwhile).
+    /// None if the account doesn't exist.
+    pub fn rounds_to_median_work(&self, account: &AccountId) -> Option<u64> {
+        let work = self.accumulated.get(account)?.work;
+        let mut works: Vec<Work> = self.accumulated.values().map(|c| c.work).collect();
+        works.sort();
+        let mid = works.len() / 2;
+        let median = if works.len() % 2 == 1 {
+            works[mid]
+        } else {
+            let (lower, upper) = (works[mid - 1], works[mid]);
+            lower + (upper - lower) / 2
+        };
+        Some(median.saturating_sub(work))
+    }
+
+    /// Returns the number of rounds until the work of the account reaches that of
+    /// the current top account, i.e. the one with the most work.
+    /// This assumes both accounts are rewarded every round, so with work increasing
+    /// by one per reward the gap between them stays constant: the account is either
+    /// already top, which is `Some(0)`, or never catches up, which is None.
+    /// None also if the account doesn't exist.
+    pub fn rounds_to_top(&self, account: &AccountId) -> Option<u64> {
+        let work = self.accumulated.get(account)?.work;
+        let top = self.accumulated.values().map(|c| c.work).max()?;
+        if work >= top {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns all accounts with the number of rewards they need to receive,
+    /// for their work to reach `target_work`, sorted by that number (and then by account).
+    /// As with `rounds_to_median_work`, this relies on work increasing by one per reward.
+    /// Accounts already at or above the target need zero rounds.
+    pub fn eta_to_work(&self, target_work: Work) -> Vec<(AccountId, u64)> {
+        let mut etas: Vec<(AccountId, u64)> = self
+            .accumulated
+            .iter()
+            .map(|(id, counter)| (*id, target_work.saturating_sub(counter.work)))
+            .collect();
+        etas.sort_by_key(|(id, rounds)| (*rounds, *id));
+        etas
+    }
+
+    /// Returns the accumulated reward at each of the requested percentiles
+    /// (each within `[0, 1]`) over all accounts, using the nearest-rank method.
+    /// The reward is zero for all percentiles when there are no accounts.
+    /// Returns `FarmingError::PercentileOutOfRange` if any percentile is out of range.
+    pub fn reward_percentiles(&self, percentiles: &[f64]) -> Result<Vec<(f64, Money)>> {
+        if percentiles.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return Err(FarmingError::PercentileOutOfRange(
+                *percentiles
+                    .iter()
+                    .find(|p| !(0.0..=1.0).contains(*p))
+                    .unwrap(),
+            ));
+        }
+        let mut rewards: Vec<Money> = self.accumulated.values().map(|c| c.reward).collect();
+        rewards.sort();
+        Ok(percentiles
+            .iter()
+            .map(|p| {
+                let rank = (p * rewards.len() as f64).ceil() as usize;
+                let reward = rewards
+                    .get(rank.saturating_sub(1))
+                    .copied()
+                    .unwrap_or_else(Money::zero);
+                (*p, reward)
+            })
+            .collect())
+    }
+
+    /// Returns the accounts that would receive at least `min`, if `total_reward`
+    /// was distributed by the algo according to the current work of all accounts.
+    /// Returns an error if the distribution fails.
+    pub fn accounts_receiving_at_least(
+        &self,
+        total_reward: Money,
+        min: Money,
+        algo: &impl RewardAlgo,
+    ) -> Result<Vec<AccountId>> {
+        let accounts_work = self
+            .accumulated
+            .iter()
+            .map(|(id, counter)| (*id, counter.work))
+            .collect();
+        let mut accounts: Vec<AccountId> = algo
+            .distribute(total_reward, accounts_work)?
+            .into_iter()
+            .filter(|(_, share)| *share >= min)
+            .map(|(id, _)| id)
+            .collect();
+        accounts.sort();
+        Ok(accounts)
+    }
+
+    /// Returns the distribution the data was rewarded with, if it was rewarded
+    /// while tracking was on, and has not been evicted since.
+    pub fn get_accumulated_distribution(&self, id: &Id) -> Option<&HashMap<AccountId, Money>> {
+        self.distributions.as_ref()?.get(id)
+    }
+
+    /// Returns the total size of the data rewarded while tracking was on,
+    /// and not evicted since, or zero if tracking is off. Saturates at the max value.
+    pub fn total_bytes_rewarded(&self) -> u64 {
+        self.rewarded_bytes
+            .iter()
+            .flat_map(|sizes| sizes.values())
+            .fold(0_u64, |total, size| total.saturating_add(*size))
+    }
+
+    /// Estimates the reward attributable to the work of the account above
+    /// `baseline_work`, as if its work was counted from that point on.
+    /// The estimate assumes every unit of work earned the same `per_work_reward`,
+    /// i.e. it is `(work - baseline_work) * per_work_reward`, saturating at the max value.
+    /// Is zero if the account is unknown or its work does not exceed the baseline.
+    pub fn reward_from_work_baseline(
+        &self,
+        account: &AccountId,
+        baseline_work: Work,
+        per_work_reward: Money,
+    ) -> Money {
+        let work = match self.accumulated.get(account) {
+            Some(counter) => counter.work.saturating_sub(baseline_work),
+            None => return Money::zero(),
+        };
+        Money::from_nano(work.saturating_mul(per_work_reward.as_nano()))
+    }
+
+    /// Returns whether the id has been rewarded, i.e. whether `accumulate` would
+    /// reject it, e.g. for pre-filtering a batch before computing distributions.
+    pub fn is_rewarded(&self, id: &Id) -> bool {
+        self.idempotency.contains(id)
+    }
+
+    /// Returns the ids of everything rewarded so far,
+    /// for replicating the idempotency separately from the accounts.
+    pub fn idempotency_snapshot(&self) -> Vec<Id> {
+        let mut ids: Vec<Id> = self.idempotency.iter().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the signed change in reward (in nanos) of every account
+    /// whose reward differs from the one in the `since` snapshot.
+    /// Increases are positive, and claims (where the account is gone) negative.
+    pub fn reward_deltas(
+        &self,
+        since: &HashMap<AccountId, RewardCounter>,
+    ) -> HashMap<AccountId, i128> {
+        let reward_of = |counter: Option<&RewardCounter>| {
+            counter.map(|c| c.reward.as_nano() as i128).unwrap_or(0)
+        };
+        self.accumulated
+            .keys()
+            .chain(since.keys())
+            .map(|id| {
+                (
+                    *id,
+                    reward_of(self.accumulated.get(id)) - reward_of(since.get(id)),
+                )
+            })
+            .filter(|(_, delta)| *delta != 0)
+            .collect()
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Cmds -------------------------------------
     /// -----------------------------------------------------------------
 
     pub fn add_account(&self, id: AccountId, work: Work) -> Result<AccountAdded> {
         if self.accumulated.contains_key(&id) {
-            return Err(Error::BalanceExists);
+            return Err(FarmingError::AccountExists);
         }
         Ok(AccountAdded { id, work })
     }
 
-    ///
+    /// Removes the account, dropping any reward it has accumulated.
+    /// Unlike `claim`, nothing is paid out, so this is for when the operator
+    /// explicitly wants the account gone, e.g. when its node has left the section.
+    pub fn remove_account(&self, id: AccountId) -> Result<AccountRemoved> {
+        if !self.accumulated.contains_key(&id) {
+            return Err(FarmingError::NoSuchAccount);
+        }
+        Ok(AccountRemoved { id })
+    }
+
+    /// Returns `FarmingError::EmptyDistribution` if the distribution is empty,
+    /// since the id would then be used up without rewarding anyone.
     pub fn accumulate(
         &self,
         id: Id,
         distribution: HashMap<AccountId, Money>,
     ) -> Result<RewardsAccumulated> {
+        self.accumulate_checked(id, distribution)
+            .map_err(FarmingError::from)
+    }
+
+    /// Same as `accumulate`, but when the data has already been rewarded and its
+    /// distribution is retained, see `track_distributions`, that distribution is
+    /// returned instead of `FarmingError::AlreadyRewarded`, e.g. for reconciling
+    /// with another Elder that rewarded it first.
+    /// Still returns `FarmingError::AlreadyRewarded` when the distribution
+    /// is not retained, either because tracking is off or it was evicted.
+    pub fn accumulate_or_recorded(
+        &self,
+        id: Id,
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<Accumulated> {
+        if let Some(recorded) = self.get_accumulated_distribution(&id) {
+            return Ok(Accumulated::Recorded(recorded.clone()));
+        }
+        self.accumulate(id, distribution).map(Accumulated::New)
+    }
+
+    /// Same as `accumulate`, but when the reward of an account would overflow,
+    /// the error identifies the account and its share, so that the caller
+    /// can drop or cap that account and retry with the rest.
+    pub fn accumulate_checked(
+        &self,
+        id: Id,
+        distribution: HashMap<AccountId, Money>,
+    ) -> std::result::Result<RewardsAccumulated, AccumulateError> {
         if self.idempotency.contains(&id) {
-            return Err(Error::DataExists);
+            return Err(AccumulateError::Other(FarmingError::AlreadyRewarded));
         }
-        for (id, amount) in &distribution {
-            if let Some(existing) = self.accumulated.get(&id) {
-                if existing.add(*amount).is_none() {
-                    return Err(Error::ExcessiveValue);
-                }
-            };
+        if distribution.is_empty() {
+            return Err(AccumulateError::Other(FarmingError::EmptyDistribution));
+        }
+        for (account, amount) in &distribution {
+            let existing = self.accumulated.get(account).cloned().unwrap_or_default();
+            if existing.add(*amount).is_none() {
+                return Err(AccumulateError::Overflow(*account, *amount));
+            }
         }
 
-        Ok(RewardsAccumulated { id, distribution })
+        Ok(RewardsAccumulated {
+            id,
+            distribution,
+            num_bytes: None,
+            seed: None,
+        })
     }
 
-    ///
+    /// As `accumulate`, but records the size of the rewarded data in the event.
+    pub fn accumulate_sized(
+        &self,
+        id: Id,
+        num_bytes: u64,
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<RewardsAccumulated> {
+        let mut e = self.accumulate(id, distribution)?;
+        e.num_bytes = Some(num_bytes);
+        Ok(e)
+    }
+
+    /// As `accumulate`, but records the seed of a probabilistic distribution
+    /// in the event, so that its draws can be audited.
+    /// See `StorageRewards::distribute_probabilistic`.
+    pub fn accumulate_seeded(
+        &self,
+        id: Id,
+        seed: [u8; 32],
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<RewardsAccumulated> {
+        let mut e = self.accumulate(id, distribution)?;
+        e.seed = Some(seed);
+        Ok(e)
+    }
+
+    /// Validates an accumulation received from a peer, by recomputing the total reward
+    /// the algo implies for `num_bytes` and `factor`, and checking that the event
+    /// does not pay out more than that, beyond a rounding tolerance of 1 nano.
+    /// Returns `FarmingError::InvalidDistribution` if it does, or if its total overflows.
+    pub fn validate_received(
+        &self,
+        event: &RewardsAccumulated,
+        num_bytes: u64,
+        factor: f64,
+        algo: &impl RewardAlgo,
+    ) -> Result<()> {
+        let expected = algo.total_reward(factor, algo.work_cost(num_bytes))?;
+        let mut paid = Money::zero();
+        for share in event.distribution.values() {
+            paid = paid
+                .checked_add(*share)
+                .ok_or(FarmingError::InvalidDistribution)?;
+        }
+        if paid.as_nano() > expected.as_nano().saturating_add(1) {
+            return Err(FarmingError::InvalidDistribution);
+        }
+        Ok(())
+    }
+
+    /// Claims the whole reward of the account, which is then removed.
+    /// Returns `FarmingError::NoSuchAccount` if there is no such account.
     pub fn claim(&self, account: AccountId) -> Result<RewardsClaimed> {
         let result = self.accumulated.get(&account);
         match result {
-            None => Err(Error::NoSuchKey),
+            None => Err(FarmingError::NoSuchAccount),
             Some(rewards) => Ok(RewardsClaimed {
                 account,
                 rewards: rewards.clone(),
@@ -89,44 +766,482 @@ impl Accumulation {
         }
     }
 
+    /// Same as `claim`, but the work of the account is transferred to the `to` account,
+    /// which is created if it does not exist, instead of being discarded.
+    /// Returns `FarmingError::TransferToSelf` if the accounts are the same,
+    /// and `FarmingError::Overflow` if the work of `to` would overflow.
+    pub fn claim_and_transfer(
+        &self,
+        from: AccountId,
+        to: AccountId,
+    ) -> Result<RewardsClaimedAndTransferred> {
+        if from == to {
+            return Err(FarmingError::TransferToSelf);
+        }
+        let claim = self.claim(from)?;
+        let existing = self.accumulated.get(&to).map(|c| c.work).unwrap_or(0);
+        if existing.checked_add(claim.rewards.work).is_none() {
+            return Err(FarmingError::Overflow);
+        }
+        Ok(RewardsClaimedAndTransferred { claim, to })
+    }
+
+    /// Same as `claim`, but with the payout vested as per the schedule.
+    /// The vesting is tracked on the returned receipt only,
+    /// and the account is removed when its claim is applied.
+    pub fn claim_vested(
+        &self,
+        account: AccountId,
+        cliff_rounds: u64,
+        vest_rounds: u64,
+    ) -> Result<VestedClaim> {
+        Ok(VestedClaim {
+            claim: self.claim(account)?,
+            cliff_rounds,
+            vest_rounds,
+        })
+    }
+
+    /// Returns how much of the vested claim is unlocked at the round,
+    /// counted from the round of the claim.
+    pub fn vested_amount(&self, claim: &VestedClaim, current_round: u64) -> Money {
+        let total = claim.claim.rewards.reward;
+        if current_round < claim.cliff_rounds {
+            return Money::zero();
+        }
+        if current_round >= claim.vest_rounds {
+            return total;
+        }
+        let unlocked = total.as_nano() as u128 * current_round as u128 / claim.vest_rounds as u128;
+        Money::from_nano(unlocked as u64)
+    }
+
+    /// Claims part of the accumulated reward of the account, which stays,
+    /// together with its work, to keep accumulating.
+    /// The event carries the claimed amount, and the current work of the account.
+    /// Returns `FarmingError::ExceedsReward` if the amount exceeds the accumulated reward.
+    pub fn claim_amount(&self, account: AccountId, amount: Money) -> Result<RewardsClaimed> {
+        let rewards = self
+            .accumulated
+            .get(&account)
+            .ok_or(FarmingError::NoSuchAccount)?;
+        if amount > rewards.reward {
+            return Err(FarmingError::ExceedsReward);
+        }
+        Ok(RewardsClaimed {
+            account,
+            rewards: RewardCounter {
+                reward: amount,
+                work: rewards.work,
+            },
+        })
+    }
+
+    /// Claims all accounts, returning the claims together with the total paid out.
+    /// Returns `FarmingError::Overflow` if the total would overflow.
+    pub fn claim_all_reporting(&self) -> Result<(Vec<RewardsClaimed>, Money)> {
+        let mut total = Money::zero();
+        let mut claims = vec![];
+        for account in self.accumulated.keys() {
+            let claim = self.claim(*account)?;
+            total = total
+                .checked_add(claim.rewards.reward)
+                .ok_or(FarmingError::Overflow)?;
+            claims.push(claim);
+        }
+        Ok((claims, total))
+    }
+
+    /// Returns work updates for the accounts whose work, as implied by their
+    /// node age (see `utils::work_from_age`), is higher than their stored work.
+    /// Work is never decreased, and never raised above the work cap, if any.
+    pub fn sync_work_from_age(&self, ages: &HashMap<AccountId, NodeAge>) -> Vec<WorkUpdated> {
+        let mut updates: Vec<WorkUpdated> = ages
+            .iter()
+            .filter_map(|(id, age)| {
+                let stored = self.accumulated.get(id)?.work;
+                let mut work = work_from_age(*age);
+                if let Some(max_work) = self.max_work {
+                    work = work.min(max_work);
+                }
+                if work > stored {
+                    Some(WorkUpdated { id: *id, work })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        updates.sort_by_key(|e| e.id);
+        updates
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Mutation ---------------------------------
     /// -----------------------------------------------------------------
 
+    /// Merges the ids of an idempotency snapshot into the existing ones.
+    /// With a cap, the ids are taken as the most recent, in the given order.
+    pub fn restore_idempotency(&mut self, ids: Vec<Id>) {
+        for id in ids {
+            self.remember(id);
+        }
+    }
+
+    /// Merges the state of another instance into this one, taking the max of
+    /// reward and work for every account, and the union of rewarded ids.
+    /// Returns the accounts known to both sides whose counters differed,
+    /// with this side's counter first and the other's second, sorted by account.
+    pub fn merge_reporting(
+        &mut self,
+        other: &Accumulation,
+    ) -> Vec<(AccountId, RewardCounter, RewardCounter)> {
+        let mut conflicts = vec![];
+        for (id, theirs) in &other.accumulated {
+            match self.accumulated.get_mut(id) {
+                None => {
+                    self.total_work += theirs.work as u128;
+                    let _ = self.accumulated.insert(*id, theirs.clone());
+                }
+                Some(ours) => {
+                    if ours != theirs {
+                        conflicts.push((*id, ours.clone(), theirs.clone()));
+                        let work = ours.work.max(theirs.work);
+                        self.total_work += (work - ours.work) as u128;
+                        ours.reward = ours.reward.max(theirs.reward);
+                        ours.work = work;
+                    }
+                }
+            }
+        }
+        for id in other.idempotency_snapshot() {
+            self.remember(id);
+        }
+        self.claimed = self.claimed.max(other.claimed);
+        conflicts.sort_by_key(|(id, _, _)| *id);
+        conflicts
+    }
+
+    /// Merges another instance into this one, such as when combining the
+    /// sub-accumulators of a split section. The rewarded ids are unioned, and for
+    /// accounts present in both, the rewards are summed, since each side paid out
+    /// for different data. The work is the max of the two, not the sum, since it
+    /// measures the same node's participation, which both sides have observed;
+    /// summing would double count it. The claimed totals are summed as well.
+    /// Returns `FarmingError::Overflow`, leaving the state unchanged, on overflow.
+    pub fn merge(&mut self, other: Accumulation) -> Result<()> {
+        let ids = other.idempotency_snapshot();
+        let mut merged = Vec::with_capacity(other.accumulated.len());
+        for (id, theirs) in other.accumulated {
+            let counter = match self.accumulated.get(&id) {
+                None => theirs,
+                Some(ours) => RewardCounter {
+                    reward: ours
+                        .reward
+                        .checked_add(theirs.reward)
+                        .ok_or(FarmingError::Overflow)?,
+                    work: ours.work.max(theirs.work),
+                },
+            };
+            merged.push((id, counter));
+        }
+        let claimed = self
+            .claimed
+            .checked_add(other.claimed)
+            .ok_or(FarmingError::Overflow)?;
+        for (id, counter) in merged {
+            self.total_work += counter.work as u128;
+            if let Some(replaced) = self.accumulated.insert(id, counter) {
+                self.total_work -= replaced.work as u128;
+            }
+        }
+        if let (Some(ours), Some(theirs)) = (&mut self.rewarded_bytes, other.rewarded_bytes) {
+            ours.extend(theirs);
+        }
+        if let (Some(ours), Some(theirs)) = (&mut self.distributions, other.distributions) {
+            ours.extend(theirs);
+        }
+        for id in ids {
+            self.remember(id);
+        }
+        self.claimed = claimed;
+        Ok(())
+    }
+
+    /// Validates the accumulation as per `accumulate`, and on success applies it.
+    /// Returns the event, e.g. for logging. Event sourcing users, who persist the
+    /// event before applying it, use `accumulate` and `apply` instead.
+    pub fn accumulate_and_apply(
+        &mut self,
+        id: Id,
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<RewardsAccumulated> {
+        let e = self.accumulate(id, distribution)?;
+        self.apply(AccumulationEvent::RewardsAccumulated(e.clone()))?;
+        Ok(e)
+    }
+
+    /// Same as `accumulate_and_apply`, but for `add_account`.
+    pub fn add_account_and_apply(&mut self, id: AccountId, work: Work) -> Result<AccountAdded> {
+        let e = self.add_account(id, work)?;
+        self.apply(AccumulationEvent::AccountAdded(e.clone()))?;
+        Ok(e)
+    }
+
+    /// Same as `accumulate_and_apply`, but for `claim`.
+    pub fn claim_and_apply(&mut self, account: AccountId) -> Result<RewardsClaimed> {
+        let e = self.claim(account)?;
+        self.apply(AccumulationEvent::RewardsClaimed(e.clone()))?;
+        Ok(e)
+    }
+
     /// Mutates state.
-    pub fn apply(&mut self, event: AccumulationEvent) {
+    /// Returns `FarmingError::Overflow`, leaving the state unchanged,
+    /// if accumulating the rewards would overflow a balance.
+    pub fn apply(&mut self, event: AccumulationEvent) -> Result<()> {
+        let crossed = self.apply_deferring_hooks(event)?;
+        if let Some(hook) = &self.soft_cap_hook {
+            for (id, reward) in &crossed {
+                hook(id, *reward);
+            }
+        }
+        Ok(())
+    }
+
+    /// The soft cap hook, if any.
+    pub(crate) fn soft_cap_hook(&self) -> Option<SoftCapHook> {
+        self.soft_cap_hook.clone()
+    }
+
+    /// Same as `apply`, but instead of calling the soft cap hook, returns the accounts
+    /// that crossed the soft cap, with their new reward, so that the caller can call
+    /// the hook later, e.g. once it has released a lock.
+    pub(crate) fn apply_deferring_hooks(
+        &mut self,
+        event: AccumulationEvent,
+    ) -> Result<Vec<(AccountId, Money)>> {
         use AccumulationEvent::*;
+        let mut crossing_soft_cap = vec![];
         match event {
             AccountAdded(e) => {
-                let _ = self.accumulated.insert(
+                self.total_work = self.total_work.saturating_add(e.work as u128);
+                if let Some(replaced) = self.accumulated.insert(
                     e.id,
                     RewardCounter {
                         reward: Money::zero(),
                         work: e.work,
                     },
-                );
+                ) {
+                    self.total_work = self.total_work.saturating_sub(replaced.work as u128);
+                }
             }
             RewardsAccumulated(e) => {
-                for (id, amount) in e.distribution {
-                    let existing = match self.accumulated.get(&id) {
+                // An event replayed out of order, or deserialized from untrusted input,
+                // may overflow a balance, so all sums are checked before anything changes.
+                let mut updates = Vec::with_capacity(e.distribution.len());
+                for (id, amount) in &e.distribution {
+                    let existing = match self.accumulated.get(id) {
                         None => Default::default(),
                         Some(acc) => acc.clone(),
                     };
-                    let accumulated = existing.add(amount).unwrap(); // this is OK, since validation shall happen before creating the event
-                    let _ = self.idempotency.insert(e.id.clone());
+                    match existing.add(*amount) {
+                        Some(accumulated) => updates.push((*id, existing, accumulated)),
+                        None => return Err(FarmingError::Overflow),
+                    }
+                }
+                if let (Some(sizes), Some(num_bytes)) = (&mut self.rewarded_bytes, e.num_bytes) {
+                    let _ = sizes.insert(e.id.clone(), num_bytes);
+                }
+                if let Some(distributions) = &mut self.distributions {
+                    let _ = distributions.insert(e.id.clone(), e.distribution);
+                }
+                for (id, existing, mut accumulated) in updates {
+                    if let Some(soft_cap) = self.soft_cap {
+                        if existing.reward < soft_cap && accumulated.reward >= soft_cap {
+                            crossing_soft_cap.push((id, accumulated.reward));
+                        }
+                    }
+                    if let Some(max_work) = self.max_work {
+                        // Saturate at the cap, but never decrease work that was added above it.
+                        accumulated.work = existing.work.max(accumulated.work.min(max_work));
+                    }
+                    self.total_work = self
+                        .total_work
+                        .saturating_sub(existing.work as u128)
+                        .saturating_add(accumulated.work as u128);
+                    self.remember(e.id.clone());
                     let _ = self.accumulated.insert(id, accumulated);
                 }
             }
             RewardsClaimed(e) => {
-                let _ = self.accumulated.remove(&e.account);
+                if let Some(counter) = self.accumulated.remove(&e.account) {
+                    self.total_work = self.total_work.saturating_sub(counter.work as u128);
+                    let claimed = self
+                        .claimed
+                        .as_nano()
+                        .saturating_add(counter.reward.as_nano());
+                    self.claimed = Money::from_nano(claimed);
+                }
+            }
+            RewardsPartiallyClaimed(e) => {
+                if let Some(counter) = self.accumulated.get_mut(&e.account) {
+                    let amount = e.rewards.reward.min(counter.reward);
+                    counter.reward = Money::from_nano(counter.reward.as_nano() - amount.as_nano());
+                    let claimed = self.claimed.as_nano().saturating_add(amount.as_nano());
+                    self.claimed = Money::from_nano(claimed);
+                }
+            }
+            WorkUpdated(e) => {
+                if let Some(counter) = self.accumulated.get_mut(&e.id) {
+                    let work = counter.work.max(e.work);
+                    self.total_work = self
+                        .total_work
+                        .saturating_add((work - counter.work) as u128);
+                    counter.work = work;
+                }
+            }
+            AccountRemoved(e) => {
+                if let Some(counter) = self.accumulated.remove(&e.id) {
+                    self.total_work = self.total_work.saturating_sub(counter.work as u128);
+                }
+            }
+            RewardsClaimedAndTransferred(e) => {
+                if let Some(counter) = self.accumulated.remove(&e.claim.account) {
+                    let claimed = self
+                        .claimed
+                        .as_nano()
+                        .saturating_add(counter.reward.as_nano());
+                    self.claimed = Money::from_nano(claimed);
+                    let to = self.accumulated.entry(e.to).or_default();
+                    // The work moves between accounts, so the total is unchanged.
+                    to.work = to.work.saturating_add(counter.work);
+                }
+            }
+        }
+        Ok(crossing_soft_cap)
+    }
+
+    /// Records the id as rewarded, evicting the oldest ids beyond the cap, if any.
+    fn remember(&mut self, id: Id) {
+        let cap = match self.idempotency_cap {
+            None => {
+                let _ = self.idempotency.insert(id);
+                return;
+            }
+            Some(cap) => cap.get(),
+        };
+        if !self.idempotency.insert(id.clone()) {
+            return;
+        }
+        self.idempotency_order.push_back(id);
+        while self.idempotency_order.len() > cap {
+            if let Some(oldest) = self.idempotency_order.pop_front() {
+                let _ = self.idempotency.remove(&oldest);
+                if let Some(sizes) = &mut self.rewarded_bytes {
+                    let _ = sizes.remove(&oldest);
+                }
+                if let Some(distributions) = &mut self.distributions {
+                    let _ = distributions.remove(&oldest);
+                }
             }
         }
     }
 }
+
+/// Only the number of rewarded ids is printed, since there can be millions of them.
+impl fmt::Debug for Accumulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Accumulation")
+            .field("rewarded_ids", &self.idempotency.len())
+            .field("idempotency_cap", &self.idempotency_cap)
+            .field("accounts", &self.accumulated.len())
+            .field("accumulated", &self.accumulated)
+            .field("claimed", &self.claimed)
+            .field("total_work", &self.total_work)
+            .field("soft_cap", &self.soft_cap)
+            .field("max_work", &self.max_work)
+            .finish()
+    }
+}
+
+/// A read-only view of an `Accumulation`, exposing only its queries.
+/// Lets reporting layers accept a view, so that they statically
+/// can not issue any commands or mutate state.
+///
+/// ```compile_fail
+/// use safe_farming::Accumulation;
+/// use safe_nd::PublicKey;
+/// use threshold_crypto::SecretKey;
+///
+/// let acc = Accumulation::new(Default::default(), Default::default(), None);
+/// let account = PublicKey::from(SecretKey::random().public_key());
+/// // Commands are not available on the view.
+/// let _ = acc.view().claim(account);
+/// ```
+#[derive(Clone, Copy)]
+pub struct AccumulationView<'a> {
+    accumulation: &'a Accumulation,
+}
+
+impl<'a> AccumulationView<'a> {
+    /// See `Accumulation::get`.
+    pub fn get(&self, account: &AccountId) -> Option<&'a RewardCounter> {
+        self.accumulation.get(account)
+    }
+
+    /// See `Accumulation::get_all`.
+    pub fn get_all(&self) -> &'a HashMap<AccountId, RewardCounter> {
+        self.accumulation.get_all()
+    }
+
+    /// See `Accumulation::total_accumulated`.
+    pub fn total_accumulated(&self) -> Money {
+        self.accumulation.total_accumulated()
+    }
+
+    /// See `Accumulation::claimed_total`.
+    pub fn claimed_total(&self) -> Money {
+        self.accumulation.claimed_total()
+    }
+
+    /// See `Accumulation::simulate_claim`.
+    pub fn simulate_claim(&self, account: &AccountId) -> Option<SupplyImpact> {
+        self.accumulation.simulate_claim(account)
+    }
+
+    /// See `Accumulation::reward_rate`.
+    pub fn reward_rate(&self, account: &AccountId) -> Option<f64> {
+        self.accumulation.reward_rate(account)
+    }
+
+    /// See `Accumulation::reward_fraction`.
+    pub fn reward_fraction(&self, account: &AccountId) -> Option<f64> {
+        self.accumulation.reward_fraction(account)
+    }
+
+    /// See `Accumulation::is_rewarded`.
+    pub fn is_rewarded(&self, id: &Id) -> bool {
+        self.accumulation.is_rewarded(id)
+    }
+
+    /// See `Accumulation::reward_deltas`.
+    pub fn reward_deltas(
+        &self,
+        since: &HashMap<AccountId, RewardCounter>,
+    ) -> HashMap<AccountId, i128> {
+        self.accumulation.reward_deltas(since)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Accumulation, AccumulationEvent};
-    use safe_nd::{Error, Money, PublicKey};
+    use super::{AccumulateError, Accumulated, Accumulation, AccumulationEvent};
+    use crate::{FarmingError, RewardsAccumulated, StorageRewards, WorkUpdated};
+    use safe_nd::{AccountId, Error, Money, PublicKey, RewardCounter};
+    use std::{
+        collections::{HashMap, HashSet},
+        num::NonZeroUsize,
+        sync::{Arc, Mutex},
+    };
     use threshold_crypto::SecretKey;
 
     macro_rules! hashmap {
@@ -140,7 +1255,7 @@ mod test {
     #[test]
     fn when_data_was_not_previously_rewarded_reward_accumulates() -> Result<(), Error> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
@@ -155,7 +1270,7 @@ mod test {
         assert!(e.distribution.len() == 1);
         assert!(e.distribution.contains_key(&account));
         assert_eq!(&reward, e.distribution.get(&account).unwrap());
-        acc.apply(AccumulationEvent::RewardsAccumulated(e));
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
         // .. and successful.
         if let Some(accumulated) = acc.get(&account) {
             assert_eq!(accumulated.reward, reward);
@@ -166,7 +1281,7 @@ mod test {
     #[test]
     fn when_data_is_already_rewarded_accumulation_is_rejected() -> Result<(), Error> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
@@ -174,7 +1289,7 @@ mod test {
 
         // Accumulate reward.
         let reward = acc.accumulate(data_hash.clone(), distribution.clone())?;
-        acc.apply(AccumulationEvent::RewardsAccumulated(reward));
+        acc.apply(AccumulationEvent::RewardsAccumulated(reward))?;
 
         // --- Act ---
         // Try same data hash again ..
@@ -183,7 +1298,7 @@ mod test {
         // .. confirm not successful.
         assert_eq!(
             acc.accumulate(data_hash, distribution),
-            Err(Error::DataExists)
+            Err(FarmingError::AlreadyRewarded)
         );
         Ok(())
     }
@@ -191,38 +1306,38 @@ mod test {
     #[test]
     fn when_account_has_reward_it_can_claim() -> Result<(), Error> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
         let distribution = hashmap![account => reward];
         let accumulation = acc.accumulate(data_hash, distribution)?;
-        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation));
+        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation))?;
 
         // --- Act + Assert ---
         // Try claim, confirm account and amount is correct.
         let e = acc.claim(account)?;
         assert!(e.account == account);
         assert!(e.rewards.reward == reward);
-        acc.apply(AccumulationEvent::RewardsClaimed(e));
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
         Ok(())
     }
 
     #[test]
-    fn when_reward_was_claimed_it_can_not_be_claimed_again() {
+    fn when_reward_was_claimed_it_can_not_be_claimed_again() -> Result<(), Error> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
         let distribution = hashmap![account => reward];
 
         let accumulation = acc.accumulate(data_hash, distribution).unwrap();
-        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation));
+        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation))?;
 
         // Claim the account reward.
         let claim = acc.claim(account).unwrap();
-        acc.apply(AccumulationEvent::RewardsClaimed(claim));
+        acc.apply(AccumulationEvent::RewardsClaimed(claim))?;
 
         // --- Act ---
         // Try claim the account reward again ..
@@ -230,13 +1345,14 @@ mod test {
 
         // --- Assert ---
         // .. confirm not successful.
-        assert_eq!(result, Err(Error::NoSuchKey))
+        assert_eq!(result, Err(FarmingError::NoSuchAccount));
+        Ok(())
     }
 
     #[test]
     fn when_account_has_no_reward_it_can_not_claim() {
         // --- Arrange ---
-        let acc = Accumulation::new(Default::default(), Default::default());
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
 
         // --- Act + Assert ---
@@ -244,22 +1360,22 @@ mod test {
         let result = acc.claim(account);
         match result {
             Ok(_) => panic!(),
-            Err(err) => assert_eq!(err, Error::NoSuchKey),
+            Err(err) => assert_eq!(err, FarmingError::NoSuchAccount),
         }
     }
 
     #[test]
-    fn when_reward_was_claimed_get_returns_none() {
+    fn when_reward_was_claimed_get_returns_none() -> Result<(), Error> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
         let distribution = hashmap![account => reward];
         let accumulation = acc.accumulate(data_hash, distribution).unwrap();
-        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation));
+        acc.apply(AccumulationEvent::RewardsAccumulated(accumulation))?;
         let claim = acc.claim(account).unwrap();
-        acc.apply(AccumulationEvent::RewardsClaimed(claim));
+        acc.apply(AccumulationEvent::RewardsClaimed(claim))?;
 
         // --- Act ---
         // Try get the account reward.
@@ -267,6 +1383,1384 @@ mod test {
 
         // --- Assert ---
         assert!(result.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reward_deltas_reports_accumulations_and_claims() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let claimer = get_random_pk();
+        let earner = get_random_pk();
+        let distribution =
+            hashmap![claimer => Money::from_nano(10), earner => Money::from_nano(20)];
+        let e = acc.accumulate(vec![1, 2, 3], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        let snapshot = acc.get_all().clone();
+
+        // --- Act ---
+        // Reward one account, and let the other claim.
+        let e = acc.accumulate(vec![4, 5, 6], hashmap![earner => Money::from_nano(5)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        let e = acc.claim(claimer)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
+        let deltas = acc.reward_deltas(&snapshot);
+
+        // --- Assert ---
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas.get(&earner), Some(&5));
+        assert_eq!(deltas.get(&claimer), Some(&-10));
+        assert!(acc.reward_deltas(acc.get_all()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn simulated_claim_matches_actual_claim() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let claimer = get_random_pk();
+        let other = get_random_pk();
+        let distribution = hashmap![claimer => Money::from_nano(10), other => Money::from_nano(20)];
+        let e = acc.accumulate(vec![1, 2, 3], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Act ---
+        let impact = match acc.simulate_claim(&claimer) {
+            Some(impact) => impact,
+            None => return Err(Error::NoSuchKey),
+        };
+
+        // --- Assert ---
+        // Nothing changed by simulating ..
+        assert_eq!(acc.total_accumulated(), Money::from_nano(30));
+        assert_eq!(acc.claimed_total(), Money::zero());
+        // .. and the simulation matches the actual claim.
+        let e = acc.claim(claimer)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
+        assert_eq!(impact.amount, Money::from_nano(10));
+        assert_eq!(impact.total_accumulated, acc.total_accumulated());
+        assert_eq!(impact.claimed_total, acc.claimed_total());
+        assert!(acc.simulate_claim(&claimer).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn when_soft_cap_is_crossed_hook_fires_without_rejecting() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let crossed = Arc::new(Mutex::new(vec![]));
+        let recorded = crossed.clone();
+        acc.set_soft_cap(Some(Money::from_nano(15)));
+        acc.on_soft_cap_crossed(Arc::new(move |id, reward| {
+            recorded.lock().unwrap().push((*id, reward))
+        }));
+        let account = get_random_pk();
+        let e = acc.accumulate(vec![1], hashmap![account => Money::from_nano(10)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert!(crossed.lock().unwrap().is_empty());
+
+        // --- Act ---
+        // Accumulate past the soft cap.
+        let e = acc.accumulate(vec![2], hashmap![account => Money::from_nano(10)])?;
+        // Validating alone does not fire, since the event may never be applied.
+        assert!(crossed.lock().unwrap().is_empty());
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Assert ---
+        // Warning fired once, and the reward was accumulated.
+        assert_eq!(
+            *crossed.lock().unwrap(),
+            vec![(account, Money::from_nano(20))]
+        );
+        assert_eq!(acc.get(&account).unwrap().reward, Money::from_nano(20));
+        let e = acc.accumulate(vec![3], hashmap![account => Money::from_nano(10)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert_eq!(crossed.lock().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn when_max_work_is_reached_work_saturates() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        acc.set_max_work(Some(4));
+        let senior = get_random_pk();
+        let junior = get_random_pk();
+        for (id, work) in [(senior, 3), (junior, 0)].iter().copied() {
+            let e = acc.add_account(id, work)?;
+            acc.apply(AccumulationEvent::AccountAdded(e))?;
+        }
+
+        // --- Act ---
+        // Reward both accounts a few times, taking the senior past the cap.
+        for i in 0..3 {
+            let distribution =
+                hashmap![senior => Money::from_nano(1), junior => Money::from_nano(1)];
+            let e = acc.accumulate(vec![i], distribution)?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+
+        // --- Assert ---
+        assert_eq!(acc.get(&senior).map(|c| c.work), Some(4));
+        assert_eq!(acc.get(&junior).map(|c| c.work), Some(3));
+        assert_eq!(
+            acc.get(&senior).map(|c| c.reward),
+            Some(Money::from_nano(3))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn claim_all_reporting_totals_the_claimed_rewards() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let accounts: Vec<_> = (0..3).map(|_| get_random_pk()).collect();
+        let distribution = accounts
+            .iter()
+            .zip(1..)
+            .map(|(id, reward)| (*id, Money::from_nano(reward * 10)))
+            .collect();
+        let e = acc.accumulate(vec![1, 2, 3], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Act ---
+        let (claims, total) = acc.claim_all_reporting()?;
+
+        // --- Assert ---
+        assert_eq!(claims.len(), 3);
+        assert_eq!(total, Money::from_nano(60));
+        let claimed_sum: u64 = claims.iter().map(|c| c.rewards.reward.as_nano()).sum();
+        assert_eq!(claimed_sum, total.as_nano());
+        Ok(())
+    }
+
+    #[test]
+    fn claim_all_reporting_rejects_overflowing_total() {
+        // --- Arrange ---
+        let counter = RewardCounter {
+            reward: Money::from_nano(u64::MAX),
+            work: 1,
+        };
+        let accumulated = hashmap![get_random_pk() => counter.clone(), get_random_pk() => counter];
+        let acc = Accumulation::new(Default::default(), accumulated, None);
+
+        // --- Act + Assert ---
+        assert_eq!(acc.claim_all_reporting(), Err(FarmingError::Overflow));
+    }
+
+    #[test]
+    fn view_exposes_the_queries() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let account = get_random_pk();
+        let e = acc.accumulate(vec![1, 2, 3], hashmap![account => Money::from_nano(10)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Act ---
+        let view = acc.view();
+
+        // --- Assert ---
+        assert_eq!(view.get(&account), acc.get(&account));
+        assert_eq!(view.get_all(), acc.get_all());
+        assert_eq!(view.total_accumulated(), Money::from_nano(10));
+        assert_eq!(view.claimed_total(), Money::zero());
+        assert_eq!(view.reward_rate(&account), Some(10.0));
+        assert_eq!(view.simulate_claim(&account), acc.simulate_claim(&account));
+        assert!(view.reward_deltas(acc.get_all()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn rounds_to_median_work_is_the_work_gap() {
+        // --- Arrange ---
+        let junior = get_random_pk();
+        let median = get_random_pk();
+        let senior = get_random_pk();
+        let counter = |work| RewardCounter {
+            reward: Money::zero(),
+            work,
+        };
+        let accumulated =
+            hashmap![junior => counter(2), median => counter(9), senior => counter(40)];
+        let acc = Accumulation::new(Default::default(), accumulated, None);
+
+        // --- Act + Assert ---
+        assert_eq!(acc.rounds_to_median_work(&junior), Some(7));
+        assert_eq!(acc.rounds_to_median_work(&median), Some(0));
+        assert_eq!(acc.rounds_to_median_work(&senior), Some(0));
+        assert_eq!(acc.rounds_to_median_work(&get_random_pk()), None);
+    }
+
+    #[test]
+    fn reward_percentiles_over_known_rewards() -> Result<(), Error> {
+        // --- Arrange ---
+        let accumulated = (1..=10)
+            .map(|reward| {
+                let counter = RewardCounter {
+                    reward: Money::from_nano(reward * 100),
+                    work: 1,
+                };
+                (get_random_pk(), counter)
+            })
+            .collect();
+        let acc = Accumulation::new(Default::default(), accumulated, None);
+
+        // --- Act ---
+        let percentiles = acc.reward_percentiles(&[0.0, 0.5, 0.9, 0.99, 1.0])?;
+
+        // --- Assert ---
+        let rewards: Vec<u64> = percentiles.iter().map(|(_, r)| r.as_nano()).collect();
+        assert_eq!(rewards, vec![100, 500, 900, 1000, 1000]);
+        assert_eq!(
+            acc.reward_percentiles(&[0.5, 1.1]),
+            Err(FarmingError::PercentileOutOfRange(1.1))
+        );
+        assert_eq!(
+            acc.reward_percentiles(&[-0.1]),
+            Err(FarmingError::PercentileOutOfRange(-0.1))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn only_high_work_accounts_receive_at_least_min() -> Result<(), Error> {
+        // --- Arrange ---
+        let senior = get_random_pk();
+        let mid = get_random_pk();
+        let junior = get_random_pk();
+        let counter = |work| RewardCounter {
+            reward: Money::zero(),
+            work,
+        };
+        let accumulated =
+            hashmap![senior => counter(60), mid => counter(30), junior => counter(10)];
+        let acc = Accumulation::new(Default::default(), accumulated, None);
+        let algo = StorageRewards::new(Money::zero());
+
+        // --- Act ---
+        // Shares are 60, 30 and 10.
+        let receiving =
+            acc.accounts_receiving_at_least(Money::from_nano(100), Money::from_nano(30), &algo)?;
+
+        // --- Assert ---
+        let mut expected = vec![senior, mid];
+        expected.sort();
+        assert_eq!(receiving, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn work_is_synced_from_age_without_decreasing() -> Result<(), Error> {
+        // --- Arrange ---
+        let behind = get_random_pk();
+        let far_behind = get_random_pk();
+        let ahead = get_random_pk();
+        let counter = |work| RewardCounter {
+            reward: Money::zero(),
+            work,
+        };
+        let accumulated =
+            hashmap![behind => counter(10), far_behind => counter(1), ahead => counter(100)];
+        let mut acc = Accumulation::new(Default::default(), accumulated, None);
+        let ages = hashmap![behind => 4, far_behind => 6, ahead => 5, get_random_pk() => 7];
+
+        // --- Act ---
+        let updates = acc.sync_work_from_age(&ages);
+
+        // --- Assert ---
+        let mut expected = vec![
+            WorkUpdated {
+                id: behind,
+                work: 16,
+            },
+            WorkUpdated {
+                id: far_behind,
+                work: 64,
+            },
+        ];
+        expected.sort_by_key(|e| e.id);
+        assert_eq!(updates, expected);
+        for e in updates {
+            acc.apply(AccumulationEvent::WorkUpdated(e))?;
+        }
+        assert_eq!(acc.get(&behind).map(|c| c.work), Some(16));
+        assert_eq!(acc.get(&far_behind).map(|c| c.work), Some(64));
+        assert_eq!(acc.get(&ahead).map(|c| c.work), Some(100));
+        assert!(acc.sync_work_from_age(&ages).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn when_idempotency_is_restored_duplicates_are_rejected() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let account = get_random_pk();
+        let data_hash = vec![1, 2, 3];
+        let distribution = hashmap![account => Money::from_nano(10)];
+        let e = acc.accumulate(data_hash.clone(), distribution.clone())?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        let snapshot = acc.idempotency_snapshot();
+        assert_eq!(snapshot, vec![data_hash.clone()]);
+
+        // --- Act ---
+        // Clear the idempotency, keeping the accounts, and restore it.
+        let mut acc = Accumulation::new(Default::default(), acc.get_all().clone(), None);
+        assert!(acc
+            .accumulate(data_hash.clone(), distribution.clone())
+            .is_ok());
+        acc.restore_idempotency(snapshot);
+
+        // --- Assert ---
+        assert_eq!(
+            acc.accumulate(data_hash, distribution),
+            Err(FarmingError::AlreadyRewarded)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reward_from_work_baseline_counts_work_above_baseline() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let account = get_random_pk();
+        let e = acc.add_account(account, 10)?;
+        acc.apply(AccumulationEvent::AccountAdded(e))?;
+        let per_work_reward = Money::from_nano(3);
+
+        // --- Act ---
+        let above = acc.reward_from_work_baseline(&account, 4, per_work_reward);
+        let below = acc.reward_from_work_baseline(&account, 12, per_work_reward);
+        let unknown = acc.reward_from_work_baseline(&get_random_pk(), 0, per_work_reward);
+
+        // --- Assert ---
+        assert_eq!(above, Money::from_nano(18));
+        assert_eq!(below, Money::zero());
+        assert_eq!(unknown, Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn accumulated_distribution_is_retained_when_tracked() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        acc.track_distributions();
+        let (first, second) = (get_random_pk(), get_random_pk());
+        let distribution = hashmap![first => Money::from_nano(10), second => Money::from_nano(5)];
+        let _ = acc.accumulate_and_apply(vec![1], distribution.clone())?;
+
+        // --- Act ---
+        // Another Elder races to reward the same data.
+        let result = acc.accumulate(vec![1], hashmap![first => Money::from_nano(15)]);
+
+        // --- Assert ---
+        assert_eq!(result, Err(FarmingError::AlreadyRewarded));
+        assert_eq!(
+            acc.get_accumulated_distribution(&vec![1]),
+            Some(&distribution)
+        );
+        assert_eq!(acc.get_accumulated_distribution(&vec![2]), None);
+        let mut untracked = Accumulation::new(Default::default(), Default::default(), None);
+        let _ = untracked.accumulate_and_apply(vec![1], distribution)?;
+        assert_eq!(untracked.get_accumulated_distribution(&vec![1]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn accumulated_distributions_are_evicted_with_their_ids() -> Result<(), Error> {
+        let mut acc =
+            Accumulation::new(Default::default(), Default::default(), NonZeroUsize::new(2));
+        acc.track_distributions();
+        let account = get_random_pk();
+        for i in 0..3 {
+            let _ = acc.accumulate_and_apply(vec![i], hashmap![account => Money::from_nano(1)])?;
+        }
+        assert_eq!(acc.get_accumulated_distribution(&vec![0]), None);
+        assert!(acc.get_accumulated_distribution(&vec![2]).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn re_accumulating_returns_the_recorded_distribution() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        acc.track_distributions();
+        let (first, second) = (get_random_pk(), get_random_pk());
+        let distribution = hashmap![first => Money::from_nano(10), second => Money::from_nano(5)];
+        let racing = hashmap![first => Money::from_nano(15)];
+
+        // --- Act ---
+        let accumulated = acc.accumulate_or_recorded(vec![1], distribution.clone())?;
+        let e = match accumulated {
+            Accumulated::New(e) => e,
+            Accumulated::Recorded(_) => panic!("the data has not been rewarded"),
+        };
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        // Another Elder races to reward the same data.
+        let recorded = acc.accumulate_or_recorded(vec![1], racing.clone())?;
+
+        // --- Assert ---
+        assert_eq!(recorded, Accumulated::Recorded(distribution.clone()));
+        assert_eq!(acc.total_accumulated(), Money::from_nano(15));
+        let mut untracked = Accumulation::new(Default::default(), Default::default(), None);
+        let _ = untracked.accumulate_and_apply(vec![1], distribution)?;
+        assert_eq!(
+            untracked.accumulate_or_recorded(vec![1], racing),
+            Err(FarmingError::AlreadyRewarded)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rewarded_bytes_are_evicted_with_their_ids() -> Result<(), Error> {
+        let mut acc =
+            Accumulation::new(Default::default(), Default::default(), NonZeroUsize::new(2));
+        acc.track_rewarded_bytes();
+        let account = get_random_pk();
+        for (i, num_bytes) in [100, 20, 3].iter().enumerate() {
+            let distribution = hashmap![account => Money::from_nano(1)];
+            let e = acc.accumulate_sized(vec![i as u8], *num_bytes, distribution)?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+        assert_eq!(acc.total_bytes_rewarded(), 23);
+        Ok(())
+    }
+
+    #[test]
+    fn total_bytes_rewarded_sums_sized_chunks() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        acc.track_rewarded_bytes();
+        let account = get_random_pk();
+        let chunks = [(vec![1], 1_024), (vec![2], 300), (vec![3], 1)];
+
+        // --- Act ---
+        for (data_hash, num_bytes) in chunks.iter().cloned() {
+            let distribution = hashmap![account => Money::from_nano(10)];
+            let e = acc.accumulate_sized(data_hash, num_bytes, distribution)?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+        // Unsized rewards are not counted.
+        let e = acc.accumulate(vec![4], hashmap![account => Money::from_nano(10)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Assert ---
+        assert_eq!(acc.total_bytes_rewarded(), 1_325);
+        let untracked = Accumulation::new(Default::default(), Default::default(), None);
+        assert_eq!(untracked.total_bytes_rewarded(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn reward_fractions_sum_to_one() {
+        // --- Arrange ---
+        let (a, b, c) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let counter = |reward| RewardCounter {
+            reward: Money::from_nano(reward),
+            work: 1,
+        };
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![a => counter(50), b => counter(30), c => counter(20)],
+            None,
+        );
+
+        // --- Act ---
+        let fractions: Vec<f64> = [a, b, c]
+            .iter()
+            .filter_map(|id| acc.reward_fraction(id))
+            .collect();
+
+        // --- Assert ---
+        assert_eq!(fractions, vec![0.5, 0.3, 0.2]);
+        assert!((fractions.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(acc.reward_fraction(&get_random_pk()), None);
+        let empty = Accumulation::new(Default::default(), hashmap![a => counter(0)], None);
+        assert_eq!(empty.reward_fraction(&a), None);
+    }
+
+    #[test]
+    fn validate_received_rejects_inflated_events() -> Result<(), Error> {
+        // --- Arrange ---
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
+        let algo = StorageRewards::new(Money::from_nano(10));
+        let (num_bytes, factor) = (90, 1.5);
+        // The work cost is 100 nanos, so the total reward is 150 nanos.
+        let account = get_random_pk();
+        let valid = acc.accumulate(
+            vec![1],
+            hashmap![account => Money::from_nano(100), get_random_pk() => Money::from_nano(50)],
+        )?;
+        let inflated = acc.accumulate(
+            vec![2],
+            hashmap![account => Money::from_nano(100), get_random_pk() => Money::from_nano(52)],
+        )?;
+
+        // --- Act + Assert ---
+        acc.validate_received(&valid, num_bytes, factor, &algo)?;
+        assert_eq!(
+            acc.validate_received(&inflated, num_bytes, factor, &algo),
+            Err(FarmingError::InvalidDistribution)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn merge_reporting_reports_disagreeing_accounts() {
+        // --- Arrange ---
+        let (agreed, disputed, theirs_only) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let mut ours = Accumulation::new(
+            Default::default(),
+            hashmap![agreed => counter(10, 1), disputed => counter(20, 5)],
+            None,
+        );
+        let theirs = Accumulation::new(
+            vec![vec![1]].into_iter().collect(),
+            hashmap![agreed => counter(10, 1), disputed => counter(15, 7), theirs_only => counter(3, 1)],
+            None,
+        );
+
+        // --- Act ---
+        let conflicts = ours.merge_reporting(&theirs);
+
+        // --- Assert ---
+        assert_eq!(conflicts, vec![(disputed, counter(20, 5), counter(15, 7))]);
+        assert_eq!(ours.get(&disputed), Some(&counter(20, 7)));
+        assert_eq!(ours.get(&agreed), Some(&counter(10, 1)));
+        assert_eq!(ours.get(&theirs_only), Some(&counter(3, 1)));
+        assert_eq!(ours.idempotency_snapshot(), vec![vec![1]]);
+    }
+
+    #[test]
+    fn merge_of_disjoint_accounts_keeps_both() -> Result<(), Error> {
+        // --- Arrange ---
+        let (left, right) = (get_random_pk(), get_random_pk());
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let mut ours =
+            Accumulation::new(Default::default(), hashmap![left => counter(10, 2)], None);
+        let theirs = Accumulation::new(Default::default(), hashmap![right => counter(5, 3)], None);
+
+        // --- Act ---
+        ours.merge(theirs)?;
+
+        // --- Assert ---
+        assert_eq!(ours.get(&left), Some(&counter(10, 2)));
+        assert_eq!(ours.get(&right), Some(&counter(5, 3)));
+        assert_eq!(ours.cached_total_work(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_of_overlapping_accounts_sums_reward_and_takes_max_work() -> Result<(), Error> {
+        // --- Arrange ---
+        let (shared, rich) = (get_random_pk(), get_random_pk());
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let mut ours = Accumulation::new(
+            Default::default(),
+            hashmap![shared => counter(10, 2), rich => counter(u64::MAX, 1)],
+            None,
+        );
+        let theirs = Accumulation::new(Default::default(), hashmap![shared => counter(5, 7)], None);
+        let overflowing =
+            Accumulation::new(Default::default(), hashmap![rich => counter(1, 1)], None);
+
+        // --- Act ---
+        ours.merge(theirs)?;
+        let result = ours.merge(overflowing);
+
+        // --- Assert ---
+        assert_eq!(ours.get(&shared), Some(&counter(15, 7)));
+        assert_eq!(ours.cached_total_work(), 8);
+        assert_eq!(result, Err(FarmingError::Overflow));
+        assert_eq!(ours.get(&rich), Some(&counter(u64::MAX, 1)));
+        Ok(())
+    }
+
+    #[test]
+    fn merge_unions_overlapping_idempotency() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut ours = Accumulation::new(
+            vec![vec![1], vec![2]].into_iter().collect(),
+            Default::default(),
+            None,
+        );
+        let theirs = Accumulation::new(
+            vec![vec![2], vec![3]].into_iter().collect(),
+            Default::default(),
+            None,
+        );
+
+        // --- Act ---
+        ours.merge(theirs)?;
+
+        // --- Assert ---
+        assert_eq!(ours.idempotency_snapshot(), vec![vec![1], vec![2], vec![3]]);
+        let result = ours.accumulate(vec![3], hashmap![get_random_pk() => Money::from_nano(1)]);
+        assert_eq!(result, Err(FarmingError::AlreadyRewarded));
+        Ok(())
+    }
+
+    #[test]
+    fn eta_to_work_orders_by_remaining_rounds() {
+        // --- Arrange ---
+        let (senior, middle, junior) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let counter = |work| RewardCounter {
+            reward: Money::zero(),
+            work,
+        };
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![junior => counter(2), senior => counter(12), middle => counter(7)],
+            None,
+        );
+
+        // --- Act ---
+        let etas = acc.eta_to_work(10);
+
+        // --- Assert ---
+        assert_eq!(etas, vec![(senior, 0), (middle, 3), (junior, 8)]);
+    }
+
+    #[test]
+    fn claims_of_boundary_counters_do_not_overflow() -> Result<(), Error> {
+        // --- Arrange ---
+        let (rich, poor, partial) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let counter = |reward| RewardCounter {
+            reward: Money::from_nano(reward),
+            work: u64::MAX,
+        };
+        let mut acc = Accumulation::new(
+            Default::default(),
+            hashmap![rich => counter(u64::MAX), poor => counter(1), partial => counter(5)],
+            None,
+        );
+
+        // --- Act ---
+        let e = acc.claim(rich)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e.clone()))?;
+
+        // --- Assert ---
+        assert_eq!(e.rewards.reward, Money::from_nano(u64::MAX));
+        assert_eq!(acc.claimed_total(), Money::from_nano(u64::MAX));
+        // The claimed total is only a statistic, so it saturates
+        // rather than blocking later claims.
+        let (claims, total) = acc.claim_all_reporting()?;
+        assert_eq!((claims.len(), total), (2, Money::from_nano(6)));
+        let e = acc.claim_amount(partial, Money::from_nano(2))?;
+        acc.apply(AccumulationEvent::RewardsPartiallyClaimed(e))?;
+        let e = acc.claim(poor)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
+        assert!(acc.get(&poor).is_none());
+        assert_eq!(
+            acc.get(&partial).map(|c| c.reward),
+            Some(Money::from_nano(3))
+        );
+        assert_eq!(acc.claimed_total(), Money::from_nano(u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn reward_weighted_avg_work_weighs_by_reward() {
+        // --- Arrange ---
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![
+                get_random_pk() => counter(30, 10),
+                get_random_pk() => counter(10, 2),
+                get_random_pk() => counter(0, 1_000)
+            ],
+            None,
+        );
+        let idle = Accumulation::new(
+            Default::default(),
+            hashmap![get_random_pk() => counter(0, 5)],
+            None,
+        );
+
+        // --- Act + Assert ---
+        // (30 * 10 + 10 * 2) / 40
+        assert_eq!(acc.reward_weighted_avg_work(), Some(8.0));
+        assert_eq!(idle.reward_weighted_avg_work(), None);
+    }
+
+    #[test]
+    fn supply_headroom_shrinks_with_issuance() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let max_supply = Money::from_nano(100);
+        let (first, second) = (get_random_pk(), get_random_pk());
+        assert_eq!(acc.supply_headroom(max_supply), max_supply);
+
+        // --- Act + Assert ---
+        let distribution = hashmap![first => Money::from_nano(30), second => Money::from_nano(20)];
+        let e = acc.accumulate(vec![1], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert_eq!(acc.supply_headroom(max_supply), Money::from_nano(50));
+
+        // Claiming does not give back any headroom.
+        let e = acc.claim(first)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
+        assert_eq!(acc.issued_total(), Money::from_nano(50));
+        assert_eq!(acc.supply_headroom(max_supply), Money::from_nano(50));
+
+        let e = acc.accumulate(vec![2], hashmap![second => Money::from_nano(60)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert_eq!(acc.supply_headroom(max_supply), Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn cached_total_work_matches_fresh_sum() -> Result<(), Error> {
+        // --- Arrange ---
+        let existing = get_random_pk();
+        let counter = RewardCounter {
+            reward: Money::zero(),
+            work: 7,
+        };
+        let mut acc = Accumulation::new(Default::default(), hashmap![existing => counter], None);
+        let fresh_sum =
+            |acc: &Accumulation| -> u128 { acc.get_all().values().map(|c| c.work as u128).sum() };
+        let (first, second) = (get_random_pk(), get_random_pk());
+
+        // --- Act + Assert ---
+        assert_eq!(acc.cached_total_work(), fresh_sum(&acc));
+        let e = acc.add_account(first, 3)?;
+        acc.apply(AccumulationEvent::AccountAdded(e))?;
+        assert_eq!(acc.cached_total_work(), fresh_sum(&acc));
+        let distribution = hashmap![first => Money::from_nano(1), second => Money::from_nano(1)];
+        let e = acc.accumulate(vec![1], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert_eq!(acc.cached_total_work(), fresh_sum(&acc));
+        acc.apply(AccumulationEvent::WorkUpdated(WorkUpdated {
+            id: second,
+            work: 20,
+        }))?;
+        assert_eq!(acc.cached_total_work(), fresh_sum(&acc));
+        let e = acc.claim(existing)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(e))?;
+        assert_eq!(acc.cached_total_work(), fresh_sum(&acc));
+        assert_eq!(acc.cached_total_work(), 24);
+        Ok(())
+    }
+
+    #[test]
+    fn partial_claims_keep_the_account() -> Result<(), Error> {
+        // --- Arrange ---
+        let account = get_random_pk();
+        let counter = |reward| RewardCounter {
+            reward: Money::from_nano(reward),
+            work: 5,
+        };
+        let mut acc =
+            Accumulation::new(Default::default(), hashmap![account => counter(100)], None);
+        acc.restore_idempotency(vec![vec![1]]);
+
+        // --- Act + Assert ---
+        // Less than the balance.
+        let e = acc.claim_amount(account, Money::from_nano(40))?;
+        assert_eq!(e.rewards, counter(40));
+        acc.apply(AccumulationEvent::RewardsPartiallyClaimed(e))?;
+        assert_eq!(acc.get(&account), Some(&counter(60)));
+
+        // More than the balance.
+        assert_eq!(
+            acc.claim_amount(account, Money::from_nano(61)),
+            Err(FarmingError::ExceedsReward)
+        );
+
+        // Equal to the balance.
+        let e = acc.claim_amount(account, Money::from_nano(60))?;
+        acc.apply(AccumulationEvent::RewardsPartiallyClaimed(e))?;
+        assert_eq!(acc.get(&account), Some(&counter(0)));
+        assert_eq!(acc.claimed_total(), Money::from_nano(100));
+        assert_eq!(acc.idempotency_snapshot(), vec![vec![1]]);
+        assert_eq!(
+            acc.claim_amount(get_random_pk(), Money::zero()),
+            Err(FarmingError::NoSuchAccount)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn vested_claims_unlock_linearly_after_the_cliff() -> Result<(), Error> {
+        // --- Arrange ---
+        let account = get_random_pk();
+        let counter = RewardCounter {
+            reward: Money::from_nano(1_000),
+            work: 5,
+        };
+        let mut acc = Accumulation::new(Default::default(), hashmap![account => counter], None);
+
+        // --- Act ---
+        let vested = acc.claim_vested(account, 10, 40)?;
+        acc.apply(AccumulationEvent::RewardsClaimed(vested.claim.clone()))?;
+
+        // --- Assert ---
+        assert_eq!(acc.get(&account), None);
+        assert_eq!(acc.vested_amount(&vested, 0), Money::zero());
+        assert_eq!(acc.vested_amount(&vested, 9), Money::zero());
+        assert_eq!(acc.vested_amount(&vested, 10), Money::from_nano(250));
+        assert_eq!(acc.vested_amount(&vested, 30), Money::from_nano(750));
+        assert_eq!(acc.vested_amount(&vested, 40), Money::from_nano(1_000));
+        assert_eq!(acc.vested_amount(&vested, 100), Money::from_nano(1_000));
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_reports_difference_to_ledger() -> Result<(), Error> {
+        // --- Arrange ---
+        let account = get_random_pk();
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let e = acc.accumulate(vec![1], hashmap![account => Money::from_nano(70)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        let e = acc.claim_amount(account, Money::from_nano(20))?;
+        acc.apply(AccumulationEvent::RewardsPartiallyClaimed(e))?;
+
+        // --- Act + Assert ---
+        assert_eq!(acc.reconcile(Money::from_nano(70))?, 0);
+        assert_eq!(acc.reconcile(Money::from_nano(65))?, 5);
+        assert_eq!(acc.reconcile(Money::from_nano(100))?, -30);
+        assert_eq!(
+            acc.reconcile(Money::from_nano(u64::MAX)),
+            Err(FarmingError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn accumulation_round_trips_through_bincode() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        for i in 0..3 {
+            let e = acc.add_account(get_random_pk(), i)?;
+            acc.apply(AccumulationEvent::AccountAdded(e))?;
+        }
+        let distribution = acc
+            .get_all()
+            .keys()
+            .map(|id| (*id, Money::from_nano(10)))
+            .collect();
+        let e = acc.accumulate(vec![1, 2, 3], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Act ---
+        let bytes = bincode::serialize(&acc).map_err(|_| Error::InvalidOperation)?;
+        let copy: Accumulation =
+            bincode::deserialize(&bytes).map_err(|_| Error::InvalidOperation)?;
+
+        // --- Assert ---
+        assert_eq!(copy.get_all(), acc.get_all());
+        assert_eq!(copy.idempotency_snapshot(), acc.idempotency_snapshot());
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn derived_state_is_rebuilt_when_deserialized() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc =
+            Accumulation::new(Default::default(), Default::default(), NonZeroUsize::new(2));
+        let account = get_random_pk();
+        let e = acc.add_account(account, 5)?;
+        acc.apply(AccumulationEvent::AccountAdded(e))?;
+        for i in 0..3 {
+            let e = acc.accumulate(vec![i], hashmap![account => Money::from_nano(1)])?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+
+        // --- Act ---
+        let bytes = bincode::serialize(&acc).map_err(|_| Error::InvalidOperation)?;
+        let mut copy: Accumulation =
+            bincode::deserialize(&bytes).map_err(|_| Error::InvalidOperation)?;
+
+        // --- Assert ---
+        assert_eq!(copy.cached_total_work(), acc.cached_total_work());
+        assert_eq!(copy.idempotency_snapshot().len(), 2);
+        // The rebuilt order still evicts beyond the cap.
+        let e = copy.accumulate(vec![9], hashmap![account => Money::from_nano(1)])?;
+        copy.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        assert_eq!(copy.idempotency_snapshot().len(), 2);
+        let e = copy.remove_account(account)?;
+        copy.apply(AccumulationEvent::AccountRemoved(e))?;
+        assert_eq!(copy.cached_total_work(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn rounds_to_top_is_zero_or_never() {
+        // --- Arrange ---
+        let (top, tied, behind) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let counter = |work| RewardCounter {
+            reward: Money::zero(),
+            work,
+        };
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![top => counter(10), tied => counter(10), behind => counter(9)],
+            None,
+        );
+
+        // --- Act + Assert ---
+        assert_eq!(acc.rounds_to_top(&top), Some(0));
+        assert_eq!(acc.rounds_to_top(&tied), Some(0));
+        // The gap stays constant when both are rewarded every round.
+        assert_eq!(acc.rounds_to_top(&behind), None);
+        assert_eq!(acc.rounds_to_top(&get_random_pk()), None);
+    }
+
+    #[test]
+    fn removed_accounts_drop_their_reward_and_work() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let (leaving, staying) = (get_random_pk(), get_random_pk());
+        let distribution =
+            hashmap![leaving => Money::from_nano(10), staying => Money::from_nano(5)];
+        let e = acc.accumulate(vec![1, 2, 3], distribution)?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Act ---
+        let e = acc.remove_account(leaving)?;
+        acc.apply(AccumulationEvent::AccountRemoved(e))?;
+
+        // --- Assert ---
+        assert!(acc.get(&leaving).is_none());
+        assert!(acc.get(&staying).is_some());
+        assert_eq!(acc.cached_total_work(), 1);
+        assert_eq!(acc.claimed_total(), Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn when_account_is_missing_it_can_not_be_removed() {
+        // --- Arrange ---
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
+
+        // --- Act + Assert ---
+        assert_eq!(
+            acc.remove_account(get_random_pk()),
+            Err(FarmingError::NoSuchAccount)
+        );
+    }
+
+    #[test]
+    fn capped_idempotency_evicts_the_oldest_ids() -> Result<(), Error> {
+        // --- Arrange ---
+        let cap = 3;
+        let mut acc = Accumulation::new(
+            Default::default(),
+            Default::default(),
+            NonZeroUsize::new(cap),
+        );
+        let account = get_random_pk();
+
+        // --- Act ---
+        for i in 0..10 {
+            let e = acc.accumulate(vec![i], hashmap![account => Money::from_nano(1)])?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+
+        // --- Assert ---
+        assert_eq!(acc.idempotency_snapshot(), vec![vec![7], vec![8], vec![9]]);
+        // Recent ids still reject duplicates ..
+        for i in 7..10 {
+            let result = acc.accumulate(vec![i], hashmap![account => Money::from_nano(1)]);
+            assert_eq!(result, Err(FarmingError::AlreadyRewarded));
+        }
+        // .. while evicted ids are rewardable again.
+        assert!(acc
+            .accumulate(vec![0], hashmap![account => Money::from_nano(1)])
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn combined_path_matches_the_two_step_path() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut manual = Accumulation::new(Default::default(), Default::default(), None);
+        let mut combined = Accumulation::new(Default::default(), Default::default(), None);
+        let (claimer, stayer) = (get_random_pk(), get_random_pk());
+
+        // --- Act ---
+        for (id, work) in &[(claimer, 2), (stayer, 5)] {
+            let e = manual.add_account(*id, *work)?;
+            manual.apply(AccumulationEvent::AccountAdded(e.clone()))?;
+            assert_eq!(combined.add_account_and_apply(*id, *work)?, e);
+        }
+        for i in 0..3 {
+            let distribution =
+                hashmap![claimer => Money::from_nano(i + 1), stayer => Money::from_nano(2 * i)];
+            let e = manual.accumulate(vec![i as u8], distribution.clone())?;
+            manual.apply(AccumulationEvent::RewardsAccumulated(e.clone()))?;
+            assert_eq!(
+                combined.accumulate_and_apply(vec![i as u8], distribution)?,
+                e
+            );
+        }
+        let e = manual.claim(claimer)?;
+        manual.apply(AccumulationEvent::RewardsClaimed(e.clone()))?;
+        assert_eq!(combined.claim_and_apply(claimer)?, e);
+
+        // --- Assert ---
+        assert_eq!(combined.get_all(), manual.get_all());
+        assert_eq!(
+            combined.idempotency_snapshot(),
+            manual.idempotency_snapshot()
+        );
+        assert_eq!(combined.claimed_total(), manual.claimed_total());
+        assert_eq!(combined.cached_total_work(), manual.cached_total_work());
+        // A rejected validation leaves the state untouched.
+        let before = combined.get_all().clone();
+        let result =
+            combined.accumulate_and_apply(vec![0], hashmap![stayer => Money::from_nano(1)]);
+        assert_eq!(result, Err(FarmingError::AlreadyRewarded));
+        assert_eq!(combined.get_all(), &before);
+        Ok(())
+    }
+
+    #[test]
+    fn parts_reconstruct_the_same_state() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        acc.track_rewarded_bytes();
+        let (first, second, claimer) = (get_random_pk(), get_random_pk(), get_random_pk());
+        for i in 0..3 {
+            let distribution = hashmap![
+                first => Money::from_nano(i + 1),
+                second => Money::from_nano(3 * i),
+                claimer => Money::from_nano(5)
+            ];
+            let e = acc.accumulate_sized(vec![i as u8], 10, distribution)?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+        let _ = acc.claim_and_apply(claimer)?;
+        let accounts = acc.get_all().clone();
+
+        // --- Act ---
+        let restored = Accumulation::from_parts(acc.into_parts(), None);
+
+        // --- Assert ---
+        assert_eq!(restored.get_all(), &accounts);
+        assert!((0..3).all(|i| restored.is_rewarded(&vec![i])));
+        assert!(!restored.is_rewarded(&vec![3]));
+        assert_eq!(restored.claimed_total(), Money::from_nano(15));
+        assert_eq!(restored.issued_total(), Money::from_nano(30));
+        assert_eq!(restored.total_bytes_rewarded(), 30);
+        Ok(())
+    }
+
+    #[test]
+    fn parts_keep_the_eviction_order() -> Result<(), Error> {
+        // --- Arrange ---
+        let cap = NonZeroUsize::new(2);
+        let mut acc = Accumulation::new(Default::default(), Default::default(), cap);
+        let account = get_random_pk();
+        // Rewarded out of sorted order.
+        for i in &[5, 1] {
+            let _ = acc.accumulate_and_apply(vec![*i], hashmap![account => Money::from_nano(1)])?;
+        }
+
+        // --- Act ---
+        let mut restored = Accumulation::from_parts(acc.into_parts(), cap);
+        let _ = restored.accumulate_and_apply(vec![9], hashmap![account => Money::from_nano(1)])?;
+
+        // --- Assert ---
+        // The oldest is evicted, not the lowest.
+        assert!(!restored.is_rewarded(&vec![5]));
+        assert!(restored.is_rewarded(&vec![1]));
+        Ok(())
+    }
+
+    #[test]
+    fn replaying_events_rebuilds_the_same_state() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let (claimer, stayer) = (get_random_pk(), get_random_pk());
+        let mut log = vec![];
+        for (id, work) in &[(claimer, 2), (stayer, 3)] {
+            log.push(AccumulationEvent::AccountAdded(
+                acc.add_account(*id, *work)?,
+            ));
+            acc.apply(log[log.len() - 1].clone())?;
+        }
+        for i in 0..3 {
+            let distribution =
+                hashmap![claimer => Money::from_nano(i + 1), stayer => Money::from_nano(2 * i)];
+            let e =
+                AccumulationEvent::RewardsAccumulated(acc.accumulate(vec![i as u8], distribution)?);
+            acc.apply(e.clone())?;
+            log.push(e);
+        }
+        let e = AccumulationEvent::RewardsClaimed(acc.claim(claimer)?);
+        acc.apply(e.clone())?;
+        log.push(e);
+
+        // --- Act ---
+        let replayed = Accumulation::from_events(log)?;
+
+        // --- Assert ---
+        assert_eq!(replayed.get_all(), acc.get_all());
+        assert_eq!(replayed.idempotency_snapshot(), acc.idempotency_snapshot());
+        assert_eq!(replayed.claimed_total(), acc.claimed_total());
+        assert_eq!(replayed.cached_total_work(), acc.cached_total_work());
+        Ok(())
+    }
+
+    #[test]
+    fn when_distribution_is_empty_accumulation_is_rejected() {
+        // --- Arrange ---
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
+
+        // --- Act ---
+        let result = acc.accumulate(vec![1, 2, 3], Default::default());
+
+        // --- Assert ---
+        assert_eq!(result, Err(FarmingError::EmptyDistribution));
+        assert!(acc.idempotency_snapshot().is_empty());
+    }
+
+    #[test]
+    fn totals_sum_rewards_and_work_of_all_accounts() -> Result<(), Error> {
+        // --- Arrange ---
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let empty = Accumulation::new(Default::default(), Default::default(), None);
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![get_random_pk() => counter(10, 1), get_random_pk() => counter(0, 7), get_random_pk() => counter(5, 2)],
+            None,
+        );
+        let overflowing = Accumulation::new(
+            Default::default(),
+            hashmap![get_random_pk() => counter(u64::MAX, u64::MAX), get_random_pk() => counter(1, 1)],
+            None,
+        );
+
+        // --- Act + Assert ---
+        assert_eq!(empty.total_outstanding_reward()?, Money::zero());
+        assert_eq!(empty.total_work()?, 0);
+        assert_eq!(acc.total_outstanding_reward()?, Money::from_nano(15));
+        assert_eq!(acc.total_work()?, 10);
+        assert_eq!(
+            overflowing.total_outstanding_reward(),
+            Err(FarmingError::Overflow)
+        );
+        assert_eq!(overflowing.total_work(), Err(FarmingError::Overflow));
+        Ok(())
+    }
+
+    #[test]
+    fn debug_output_counts_instead_of_listing_ids() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        for i in 0..3 {
+            let e = acc.accumulate(vec![i], hashmap![get_random_pk() => Money::from_nano(1)])?;
+            acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        }
+
+        // --- Act ---
+        let output = format!("{:?}", acc);
+
+        // --- Assert ---
+        assert!(output.contains("rewarded_ids: 3"));
+        assert!(output.contains("accounts: 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn data_is_rewarded_once_accumulation_is_applied() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let data_hash = vec![1, 2, 3];
+        let e = acc.accumulate(
+            data_hash.clone(),
+            hashmap![get_random_pk() => Money::from_nano(10)],
+        )?;
+        assert!(!acc.is_rewarded(&data_hash));
+
+        // --- Act ---
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Assert ---
+        assert!(acc.is_rewarded(&data_hash));
+        assert!(acc.view().is_rewarded(&data_hash));
+        assert!(!acc.is_rewarded(&vec![4, 5, 6]));
+        Ok(())
+    }
+
+    #[test]
+    fn claimed_work_is_transferred_to_a_new_account() -> Result<(), Error> {
+        // --- Arrange ---
+        let (from, to) = (get_random_pk(), get_random_pk());
+        let counter = RewardCounter {
+            reward: Money::from_nano(10),
+            work: 7,
+        };
+        let mut acc = Accumulation::new(Default::default(), hashmap![from => counter], None);
+
+        // --- Act ---
+        let e = acc.claim_and_transfer(from, to)?;
+        acc.apply(AccumulationEvent::RewardsClaimedAndTransferred(e))?;
+
+        // --- Assert ---
+        assert!(acc.get(&from).is_none());
+        let transferred = acc.get(&to).ok_or(Error::NoSuchKey)?;
+        assert_eq!(transferred.reward, Money::zero());
+        assert_eq!(transferred.work, 7);
+        assert_eq!(acc.claimed_total(), Money::from_nano(10));
+        assert_eq!(acc.cached_total_work(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn claimed_work_is_added_to_an_existing_account() -> Result<(), Error> {
+        // --- Arrange ---
+        let (from, to) = (get_random_pk(), get_random_pk());
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let mut acc = Accumulation::new(
+            Default::default(),
+            hashmap![from => counter(10, 7), to => counter(5, 3)],
+            None,
+        );
+
+        // --- Act ---
+        let e = acc.claim_and_transfer(from, to)?;
+        acc.apply(AccumulationEvent::RewardsClaimedAndTransferred(e))?;
+
+        // --- Assert ---
+        assert_eq!(acc.get(&to), Some(&counter(5, 10)));
+        assert_eq!(acc.claimed_total(), Money::from_nano(10));
+        assert_eq!(acc.cached_total_work(), 10);
+        assert_eq!(
+            acc.claim_and_transfer(to, to),
+            Err(FarmingError::TransferToSelf)
+        );
+        assert_eq!(
+            acc.claim_and_transfer(from, to),
+            Err(FarmingError::NoSuchAccount)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn checked_accumulation_reports_the_overflowing_account() -> Result<(), Error> {
+        // --- Arrange ---
+        let (full, other) = (get_random_pk(), get_random_pk());
+        let counter = RewardCounter {
+            reward: Money::from_nano(u64::MAX - 1),
+            work: 1,
+        };
+        let acc = Accumulation::new(Default::default(), hashmap![full => counter], None);
+        let distribution = hashmap![full => Money::from_nano(2), other => Money::from_nano(2)];
+
+        // --- Act ---
+        let result = acc.accumulate_checked(vec![1], distribution.clone());
+
+        // --- Assert ---
+        assert_eq!(
+            result,
+            Err(AccumulateError::Overflow(full, Money::from_nano(2)))
+        );
+        assert_eq!(
+            acc.accumulate(vec![1], distribution),
+            Err(FarmingError::Overflow)
+        );
+        // Without the offender, the rest accumulates.
+        assert!(acc
+            .accumulate_checked(vec![1], hashmap![other => Money::from_nano(2)])
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn iterators_cover_all_accounts() {
+        // --- Arrange ---
+        let counter = |work| RewardCounter {
+            reward: Money::from_nano(work * 10),
+            work,
+        };
+        let acc = Accumulation::new(
+            Default::default(),
+            hashmap![get_random_pk() => counter(1), get_random_pk() => counter(2), get_random_pk() => counter(3)],
+            None,
+        );
+
+        // --- Act ---
+        let counters: HashMap<AccountId, RewardCounter> =
+            acc.iter().map(|(id, c)| (*id, c.clone())).collect();
+        let accounts: HashSet<&AccountId> = acc.accounts().collect();
+
+        // --- Assert ---
+        assert_eq!(&counters, acc.get_all());
+        assert_eq!(accounts, acc.get_all().keys().collect());
+    }
+
+    #[test]
+    fn applying_an_overflowing_event_fails_without_changing_state() -> Result<(), Error> {
+        // --- Arrange ---
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let (rich, other) = (get_random_pk(), get_random_pk());
+        let e = acc.accumulate(vec![1], hashmap![rich => Money::from_nano(u64::MAX)])?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+        let before = acc.get_all().clone();
+        // Hand-crafted, as `accumulate` would have rejected it.
+        let overflowing = RewardsAccumulated {
+            id: vec![2],
+            distribution: hashmap![other => Money::from_nano(1), rich => Money::from_nano(1)],
+            num_bytes: None,
+            seed: None,
+        };
+
+        // --- Act ---
+        let result = acc.apply(AccumulationEvent::RewardsAccumulated(overflowing));
+
+        // --- Assert ---
+        assert_eq!(result, Err(FarmingError::Overflow));
+        assert_eq!(acc.get_all(), &before);
+        assert!(!acc.is_rewarded(&vec![2]));
+        Ok(())
+    }
+
+    #[test]
+    fn reward_rate_is_reward_per_work() {
+        // --- Arrange ---
+        let senior = get_random_pk();
+        let junior = get_random_pk();
+        let idle = get_random_pk();
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        let accumulated = hashmap![
+            senior => counter(300, 100),
+            junior => counter(15, 10),
+            idle => counter(0, 0)
+        ];
+        let acc = Accumulation::new(Default::default(), accumulated, None);
+
+        // --- Act + Assert ---
+        assert_eq!(acc.reward_rate(&senior), Some(3.0));
+        assert_eq!(acc.reward_rate(&junior), Some(1.5));
+        assert_eq!(acc.reward_rate(&idle), None);
+        assert_eq!(acc.reward_rate(&get_random_pk()), None);
     }
 
     fn get_random_pk() -> PublicKey {
@@ -7,8 +7,17 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
+use super::{
+    error::{FarmingError, Result},
+    utils::xor_distance,
+    RewardsAccumulated,
+};
 use safe_nd::{AccountId, Money, Work};
-use std::{cmp::Ordering, collections::HashMap};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+use tiny_keccak::{Hasher, Sha3};
 
 /// This algo allows for setting a base cost together with a
 /// cost proportional to some work, as measured by a minimum work unit.
@@ -16,33 +25,544 @@ use std::{cmp::Ordering, collections::HashMap};
 pub trait RewardAlgo {
     /// Set the base cost of work.
     fn set(&mut self, base_cost: Money);
+    /// Get the current base cost of work.
+    fn base_cost(&self) -> Money;
     /// Get the cost of work for the specified number of reward units.
     /// It can be simple, 1 RU == 1 unit of money (+ base cost). Or something else.
     fn work_cost(&self, reward_units: u64) -> Money;
     /// Get the total reward implied by the work cost,
     /// as scaled by a factor representing a function of parameters
     /// relevant to the implementing layer.
-    fn total_reward(&self, factor: f64, work_cost: Money) -> Money;
+    /// The factor must be finite and greater than 0,
+    /// or `FarmingError::FactorOutOfRange` is returned.
+    fn total_reward(&self, factor: f64, work_cost: Money) -> Result<Money>;
     /// Returns the distribution of the total_reward, between
     /// the accounts supplied, proportionally to their accumulated work.
+    /// Returns an error if the shares can not be reconciled with the total reward.
     fn distribute(
         &self,
         total_reward: Money,
         accounts_work: HashMap<AccountId, Work>,
-    ) -> HashMap<AccountId, Money>;
+    ) -> Result<HashMap<AccountId, Money>>;
+    /// Same as `distribute`, but with the sum of all work in `accounts_work` precomputed,
+    /// e.g. as cached by `Accumulation::cached_total_work`, which spares algos
+    /// that need it from summing it up again. It must equal that sum, and where it is
+    /// used, it is checked to be no less, or `FarmingError::InvalidTotalWork` is returned.
+    /// By default, it is just ignored.
+    fn distribute_with_total_work(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+    ) -> Result<HashMap<AccountId, Money>> {
+        let _ = all_work;
+        self.distribute(total_reward, accounts_work)
+    }
+    /// Same as `distribute_with_total_work`, but for the reward of a specific piece of data.
+    /// Algos can use the data hash as a seed shared by all Elders, e.g. to reproducibly
+    /// pick the accounts receiving the remainder nanos when shares are rounded down.
+    /// The proportional algos give those nanos one by one to the accounts with work,
+    /// closest to the data hash by XOR distance. This matters when the total reward
+    /// is smaller than the number of accounts, where most shares round down to zero.
+    /// All Elders rewarding the same data thus reach the same distribution.
+    /// By default, the data hash is just ignored.
+    fn distribute_for_data(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+        data_hash: &[u8],
+    ) -> Result<HashMap<AccountId, Money>> {
+        let _ = data_hash;
+        self.distribute_with_total_work(total_reward, accounts_work, all_work)
+    }
+    /// Splits a deposit of the work cost for `num_bytes`, as posted by the uploader,
+    /// into the part paid as reward to the storers and the part refunded to the uploader.
+    /// The reward part is the `total_reward` for the factor, capped at the deposit,
+    /// so the two parts always sum to the work cost. With a factor above 1, the
+    /// reward exceeding the deposit is not covered by it, and nothing is refunded.
+    /// Returns an error if the factor is invalid, as per `total_reward`.
+    fn split_deposit(&self, num_bytes: u64, factor: f64) -> Result<(Money, Money)> {
+        let deposit = self.work_cost(num_bytes);
+        let reward = self.total_reward(factor, deposit)?.min(deposit);
+        let refund = Money::from_nano(deposit.as_nano() - reward.as_nano());
+        Ok((reward, refund))
+    }
+}
+
+/// Derives the factor by which rewards are scaled,
+/// from parameters describing the state of the network.
+pub trait FactorFn {
+    /// Returns the factor for the node count, section count
+    /// and percent (0 to 100) of storage filled.
+    fn factor(&self, node_count: u64, section_count: u64, percent_filled: f64) -> f64;
+}
+
+/// A factor linear in the percent filled, from `min` when empty to `max` when full,
+/// so that rewards increase as storage gets scarce.
+/// Node and section counts are not considered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearFactor {
+    /// The factor when nothing is filled.
+    pub min: f64,
+    /// The factor when everything is filled.
+    pub max: f64,
+}
+
+impl FactorFn for LinearFactor {
+    fn factor(&self, _node_count: u64, _section_count: u64, percent_filled: f64) -> f64 {
+        let filled = percent_filled.clamp(0.0, 100.0) / 100.0;
+        self.min + (self.max - self.min) * filled
+    }
+}
+
+/// How the total reward is rounded to whole nanos.
+/// All Elders must use the same mode, or they will not agree on the rewards,
+/// and BFT does not hold.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RoundingMode {
+    /// Round down, e.g. for conservative issuance when the factor is above 1.
+    Floor,
+    /// Round up.
+    Ceil,
+    /// Round to the nearest nano, with halves away from zero. This is the default.
+    Nearest,
 }
 
 /// Cost of, and rewards for, storage.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StorageRewards {
     base_cost: Money,
+    allow_zero_factor: bool,
+    max_share: Option<Money>,
+    min_reward: Money,
+    rounding_mode: RoundingMode,
 }
 
 impl StorageRewards {
     /// Passed in is the base cost
     /// for buying a unit of work.
     pub fn new(base_cost: Money) -> Self {
-        Self { base_cost }
+        Self::with_rounding_mode(base_cost, RoundingMode::Nearest)
+    }
+
+    /// Same as `new`, but with the total reward rounded as per the mode.
+    /// All Elders must agree on the mode.
+    pub fn with_rounding_mode(base_cost: Money, rounding_mode: RoundingMode) -> Self {
+        Self {
+            base_cost,
+            allow_zero_factor: false,
+            max_share: None,
+            min_reward: Money::zero(),
+            rounding_mode,
+        }
+    }
+
+    /// The mode the total reward is rounded with.
+    pub fn rounding_mode(&self) -> RoundingMode {
+        self.rounding_mode
+    }
+
+    /// Caps how much any single account can receive from one distribution,
+    /// so that long-lived accounts can not take a disproportionate share.
+    /// The excess over the cap is redistributed proportionally among the
+    /// accounts still under it, until none exceeds it. If every account with work
+    /// is capped, what is left can not be distributed, and `distribute` returns
+    /// `FarmingError::Undistributed`; see `distribute_capped`.
+    /// `None` removes the cap.
+    pub fn set_max_share(&mut self, max_share: Option<Money>) {
+        self.max_share = max_share;
+    }
+
+    /// Drops accounts whose share would be below the min reward from distributions,
+    /// so that dust does not clutter the accumulated state. What they would have got
+    /// is distributed among the remaining accounts, proportionally to their work.
+    /// If no account would reach the min reward, none is dropped.
+    /// Zero, the default, drops no account.
+    pub fn set_min_reward(&mut self, min_reward: Money) {
+        self.min_reward = min_reward;
+    }
+
+    /// Same as `distribute`, but also returns what is left undistributed,
+    /// which is zero unless every account with work is capped by the max share.
+    pub fn distribute_capped(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+    ) -> Result<(HashMap<AccountId, Money>, Money)> {
+        let all_work = total_work(&accounts_work);
+        self.capped_distribution(total_reward, accounts_work, all_work, None)
+    }
+
+    /// Distributes the exact proportional shares of the total reward, rounded down,
+    /// and returns the remainder that could not be allocated, instead of assigning it
+    /// to some account. The section can then keep the dust, i.e. recycle it.
+    /// As with `distribute`, accounts below the min reward are dropped, and shares over
+    /// the max share are clamped, with the excess redistributed among the accounts
+    /// still under it; what no account can take is part of the remainder.
+    /// The shares and the remainder always sum to the total reward.
+    /// If no account has work, nothing is distributed.
+    pub fn distribute_with_remainder(
+        &self,
+        total_reward: Money,
+        mut accounts_work: HashMap<AccountId, Work>,
+    ) -> (HashMap<AccountId, Money>, Money) {
+        let total_reward = total_reward.as_nano();
+        let floored_shares = |accounts_work: &HashMap<AccountId, Work>| {
+            let all_work = total_work(accounts_work);
+            accounts_work
+                .iter()
+                .map(|(id, work)| (*id, floored_share(total_reward, *work, all_work)))
+                .collect::<HashMap<_, _>>()
+        };
+        let mut shares = floored_shares(&accounts_work);
+        loop {
+            let dust: Vec<AccountId> = shares
+                .iter()
+                .filter(|(_, share)| **share < self.min_reward.as_nano())
+                .map(|(id, _)| *id)
+                .collect();
+            if dust.is_empty() || dust.len() == shares.len() {
+                break;
+            }
+            for id in &dust {
+                let _ = accounts_work.remove(id);
+            }
+            shares = floored_shares(&accounts_work);
+        }
+        if let Some(cap) = self.max_share.map(|cap| cap.as_nano()) {
+            loop {
+                let mut excess = 0;
+                for share in shares.values_mut().filter(|share| **share > cap) {
+                    excess += *share - cap;
+                    *share = cap;
+                }
+                let under_cap: HashMap<AccountId, Work> = accounts_work
+                    .iter()
+                    .filter(|(id, work)| **work > 0 && shares[id] < cap)
+                    .map(|(id, work)| (*id, *work))
+                    .collect();
+                if excess == 0 || under_cap.is_empty() {
+                    break;
+                }
+                let under_cap_work = total_work(&under_cap);
+                for (id, work) in &under_cap {
+                    if let Some(share) = shares.get_mut(id) {
+                        *share += floored_share(excess, *work, under_cap_work);
+                    }
+                }
+            }
+        }
+        let distributed: u64 = shares.values().sum();
+        let shares = shares
+            .into_iter()
+            .map(|(id, share)| (id, Money::from_nano(share)))
+            .collect();
+        (shares, Money::from_nano(total_reward - distributed))
+    }
+
+    /// Distributes the total reward with shares that are proportional to work on average,
+    /// also when the total reward is less than the number of accounts, where most
+    /// rounded shares would be zero. The remainder of `distribute_with_remainder`
+    /// is awarded one nano at a time, to a winner drawn by weighted sampling over the work,
+    /// seeded by a random value shared by the section, e.g. derived from a BLS signature.
+    /// All Elders with the same seed thus reach the same distribution.
+    /// Accounts dropped below the min reward, or at the max share, are not drawn.
+    /// If no account has work, all are weighted the same.
+    /// Returns `FarmingError::Undistributed` if there is no account left to draw.
+    pub fn distribute_probabilistic(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        seed: [u8; 32],
+    ) -> Result<HashMap<AccountId, Money>> {
+        let accounts_work: HashMap<_, _> = if total_work(&accounts_work) == 0 {
+            accounts_work.keys().map(|id| (*id, 1)).collect()
+        } else {
+            accounts_work
+        };
+        let (mut shares, remainder) =
+            self.distribute_with_remainder(total_reward, accounts_work.clone());
+        let cap = self.max_share.map_or(u64::MAX, |cap| cap.as_nano());
+        // Ordered the same way on all Elders.
+        let mut candidates: Vec<(AccountId, Work)> = accounts_work
+            .into_iter()
+            .filter(|(id, work)| {
+                *work > 0 && matches!(shares.get(id), Some(share) if share.as_nano() < cap)
+            })
+            .collect();
+        candidates.sort();
+        let cumulative_work = |candidates: &[(AccountId, Work)]| {
+            let mut all_work = 0_u128;
+            candidates
+                .iter()
+                .map(|(_, work)| {
+                    all_work += *work as u128;
+                    all_work
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut cumulative = cumulative_work(&candidates);
+        for round in 0..remainder.as_nano() {
+            let all_work = match cumulative.last() {
+                Some(all_work) => *all_work,
+                None => {
+                    let leftover = remainder.as_nano() - round;
+                    return Err(FarmingError::Undistributed(Money::from_nano(leftover)));
+                }
+            };
+            let draw = seeded_draw(&seed, round, all_work);
+            let index = cumulative
+                .iter()
+                .position(|sum| *sum > draw)
+                .unwrap_or(candidates.len() - 1);
+            let share = shares
+                .entry(candidates[index].0)
+                .or_insert_with(Money::zero);
+            *share = Money::from_nano(share.as_nano() + 1);
+            if share.as_nano() >= cap {
+                let _ = candidates.remove(index);
+                cumulative = cumulative_work(&candidates);
+            }
+        }
+        Ok(shares)
+    }
+
+    /// Replays the draws of a probabilistic distribution from the seed recorded
+    /// in the event, see `distribute_probabilistic`, and returns whether they
+    /// reach the distribution of the event, whose total is the total reward.
+    /// Returns false if the event records no seed.
+    pub fn verify_probabilistic(
+        &self,
+        event: &RewardsAccumulated,
+        accounts_work: &HashMap<AccountId, Work>,
+    ) -> bool {
+        let seed = match event.seed {
+            Some(seed) => seed,
+            None => return false,
+        };
+        let mut total_reward = Money::zero();
+        for share in event.distribution.values() {
+            total_reward = match total_reward.checked_add(*share) {
+                Some(total_reward) => total_reward,
+                None => return false,
+            };
+        }
+        matches!(
+            self.distribute_probabilistic(total_reward, accounts_work.clone(), seed),
+            Ok(replayed) if replayed == event.distribution
+        )
+    }
+
+    /// A factor of 0 means no issuance at all, while the rewarded
+    /// data still consumes its idempotency slot. Since that is more likely
+    /// to be a bug than a decision, it is rejected by default.
+    /// Use this to explicitly allow (or disallow again) a zero factor.
+    pub fn allow_zero_factor(&mut self, allow: bool) {
+        self.allow_zero_factor = allow;
+    }
+
+    /// Returns the net-neutral issuance point: the factor at which the total reward
+    /// equals the work cost, so that rewarding neither issues new money nor recycles any.
+    /// Since the reward is proportional to the whole work cost, base cost included,
+    /// this is 1 for `StorageRewards`.
+    pub fn neutral_factor(&self) -> f64 {
+        1.0
+    }
+
+    /// Returns the smallest factor for which `total_reward` for storing `num_bytes`
+    /// rounds to at least 1 nano, so that a round does not accidentally pay nobody.
+    /// Returns infinity if the work cost is zero, since then no factor pays anything.
+    pub fn min_nonzero_factor(&self, num_bytes: u64) -> f64 {
+        let work_cost = self.work_cost(num_bytes);
+        if work_cost.as_nano() == 0 {
+            return f64::INFINITY;
+        }
+        let pays = |factor: f64| {
+            self.total_reward(factor, work_cost)
+                .map(|reward| reward.as_nano() > 0)
+                .unwrap_or(false)
+        };
+        // Whether a factor pays grows with the factor, and so do the bits
+        // of positive floats, so the boundary is binary searched over the bits.
+        let mut paying = 1.0 / work_cost.as_nano() as f64;
+        while !pays(paying) {
+            paying *= 2.0;
+        }
+        let (mut below, mut paying) = (0.0_f64.to_bits(), paying.to_bits());
+        while paying - below > 1 {
+            let mid = below + (paying - below) / 2;
+            if pays(f64::from_bits(mid)) {
+                paying = mid;
+            } else {
+                below = mid;
+            }
+        }
+        f64::from_bits(paying)
+    }
+
+    /// Returns the factor actually applied by `total_reward`, after rounding,
+    /// i.e. `total_reward / work_cost`, which can differ slightly from the requested factor.
+    /// When the work cost is zero there is nothing to scale, and the requested factor is returned.
+    /// Returns an error if the factor is invalid, as per `total_reward`.
+    pub fn effective_factor(&self, num_bytes: u64, requested_factor: f64) -> Result<f64> {
+        let work_cost = self.work_cost(num_bytes);
+        let total_reward = self.total_reward(requested_factor, work_cost)?;
+        if work_cost.as_nano() == 0 {
+            return Ok(requested_factor);
+        }
+        Ok(total_reward.as_nano() as f64 / work_cost.as_nano() as f64)
+    }
+
+    /// Returns how many passes over all shares `distribute` needs,
+    /// to remove the excess when the rounded shares sum to more than the total reward.
+    /// Since every share is rounded to the nearest nano, the excess is less than
+    /// half the number of shares rounded up, so a single pass is always enough;
+    /// this allows tests to catch inputs where that would not hold.
+    pub fn reconciliation_iterations(
+        &self,
+        total_reward: Money,
+        accounts_work: &HashMap<AccountId, Work>,
+    ) -> u64 {
+        let mut shares: Vec<u64> = work_groups(
+            total_reward.as_nano(),
+            accounts_work,
+            total_work(accounts_work),
+        )
+        .into_iter()
+        .flat_map(|group| vec![group.share; group.ids.len()])
+        .collect();
+        let shares_sum: u128 = shares.iter().map(|share| *share as u128).sum();
+        let mut diff = shares_sum.saturating_sub(total_reward.as_nano() as u128);
+        let mut iterations = 0;
+        while diff > 0 {
+            iterations += 1;
+            for share in shares.iter_mut().filter(|share| **share >= 1) {
+                if 0 == diff {
+                    break;
+                }
+                *share -= 1;
+                diff -= 1;
+            }
+        }
+        iterations
+    }
+
+    /// Returns the share the excluded account would have received,
+    /// had it not been excluded from the distribution of `total_reward`,
+    /// i.e. what it forfeits. `accounts_work` is expected to include it;
+    /// the cost is zero if it does not.
+    /// Returns an error if the distribution fails.
+    pub fn exclusion_cost(
+        &self,
+        total_reward: Money,
+        accounts_work: &HashMap<AccountId, Work>,
+        excluded: &AccountId,
+    ) -> Result<Money> {
+        if !accounts_work.contains_key(excluded) {
+            return Ok(Money::zero());
+        }
+        Ok(self
+            .distribute(total_reward, accounts_work.clone())?
+            .get(excluded)
+            .copied()
+            .unwrap_or_else(Money::zero))
+    }
+
+    /// Returns how far (in signed nanos) each account's share in the distribution
+    /// is from its ideal proportional share, i.e. `total_reward * work / all_work`.
+    /// This quantifies the fairness cost of the integer reconciliation in `distribute`.
+    pub fn share_deviation(
+        &self,
+        distribution: &HashMap<AccountId, Money>,
+        total_reward: Money,
+        accounts_work: &HashMap<AccountId, Work>,
+    ) -> HashMap<AccountId, i64> {
+        let all_work = total_work(accounts_work);
+        accounts_work
+            .iter()
+            .map(|(id, work)| {
+                let ideal = total_reward.as_nano() as f64 * *work as f64 / all_work as f64;
+                let share = distribution.get(id).map(|s| s.as_nano()).unwrap_or(0);
+                (*id, (share as f64 - ideal).round() as i64)
+            })
+            .collect()
+    }
+
+    /// The proportional distribution, without the accounts below the min reward,
+    /// and with shares over the max share (if any) clamped, and their excess
+    /// redistributed among the accounts with work still under it.
+    /// Returns the shares, and the excess left when there are no such accounts.
+    fn capped_distribution(
+        &self,
+        total_reward: Money,
+        mut accounts_work: HashMap<AccountId, Work>,
+        mut all_work: u128,
+        data_hash: Option<&[u8]>,
+    ) -> Result<(HashMap<AccountId, Money>, Money)> {
+        if self.max_share.is_none() && self.min_reward == Money::zero() {
+            let shares =
+                proportional_distribution(total_reward, accounts_work, all_work, data_hash)?;
+            return Ok((shares, Money::zero()));
+        }
+        let mut shares =
+            proportional_distribution(total_reward, accounts_work.clone(), all_work, data_hash)?;
+        loop {
+            let dust: Vec<AccountId> = shares
+                .iter()
+                .filter(|(_, share)| **share < self.min_reward)
+                .map(|(id, _)| *id)
+                .collect();
+            if dust.is_empty() || dust.len() == shares.len() {
+                break;
+            }
+            for id in &dust {
+                let _ = accounts_work.remove(id);
+            }
+            all_work = total_work(&accounts_work);
+            shares = proportional_distribution(
+                total_reward,
+                accounts_work.clone(),
+                all_work,
+                data_hash,
+            )?;
+        }
+        let cap = match self.max_share {
+            None => return Ok((shares, Money::zero())),
+            Some(cap) => cap,
+        };
+        loop {
+            let mut excess = 0;
+            for share in shares.values_mut().filter(|share| **share > cap) {
+                excess += share.as_nano() - cap.as_nano();
+                *share = cap;
+            }
+            if excess == 0 {
+                return Ok((shares, Money::zero()));
+            }
+            let under_cap: HashMap<AccountId, Work> = accounts_work
+                .iter()
+                .filter(|(id, work)| **work > 0 && shares[id] < cap)
+                .map(|(id, work)| (*id, *work))
+                .collect();
+            if under_cap.is_empty() {
+                return Ok((shares, Money::from_nano(excess)));
+            }
+            let under_cap_work = total_work(&under_cap);
+            let extras = proportional_distribution(
+                Money::from_nano(excess),
+                under_cap,
+                under_cap_work,
+                data_hash,
+            )?;
+            for (id, extra) in extras {
+                if let Some(share) = shares.get_mut(&id) {
+                    *share = Money::from_nano(share.as_nano() + extra.as_nano());
+                }
+            }
+        }
     }
 }
 
@@ -88,6 +608,11 @@ impl RewardAlgo for StorageRewards {
         self.base_cost = base_cost;
     }
 
+    /// The base cost, as last set.
+    fn base_cost(&self) -> Money {
+        self.base_cost
+    }
+
     /// Here, reward units are the
     /// number of bytes to store.
     fn work_cost(&self, num_bytes: u64) -> Money {
@@ -100,98 +625,776 @@ impl RewardAlgo for StorageRewards {
     /// relevant to the implementing layer.
     /// In SAFE Network context, the factor could be the
     /// output of a function of node count, section count, percent filled etc. etc.
-    fn total_reward(&self, factor: f64, work_cost: Money) -> Money {
-        let amount = factor * work_cost.as_nano() as f64;
-        Money::from_nano(amount.round() as u64)
+    /// A factor of 0 is only accepted if zero factors have been allowed.
+    fn total_reward(&self, factor: f64, work_cost: Money) -> Result<Money> {
+        check_factor(factor, self.allow_zero_factor)?;
+        Ok(scaled_reward(factor, work_cost, self.rounding_mode))
     }
 
-    #[allow(clippy::needless_range_loop)]
     /// Distribute the reward
     /// according to the accumulated work
     /// associated with the ids.
     /// Also returns those who got 0 reward
     /// (when their work or total_reward wasn't high enough).
+    /// Nanos left over when shares are rounded go to the largest share,
+    /// with ties broken by the greatest account id.
+    /// The work of all accounts is summed as u128, so it can not
+    /// overflow for any realistic number of accounts (fewer than 2^64).
+    /// Returns `FarmingError::Undistributed` if the max share leaves part
+    /// of the reward undistributed; use `distribute_capped` to allow that.
     fn distribute(
         &self,
         total_reward: Money,
         accounts_work: HashMap<AccountId, Work>,
-    ) -> HashMap<AccountId, Money> {
-        //
-        let total_reward = total_reward.as_nano();
-        let all_work: Work = accounts_work.values().sum();
+    ) -> Result<HashMap<AccountId, Money>> {
+        let all_work = total_work(&accounts_work);
+        self.distribute_with_total_work(total_reward, accounts_work, all_work)
+    }
+
+    /// See `distribute`.
+    fn distribute_with_total_work(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+    ) -> Result<HashMap<AccountId, Money>> {
+        let (shares, leftover) =
+            self.capped_distribution(total_reward, accounts_work, all_work, None)?;
+        fully_distributed(shares, leftover)
+    }
+
+    /// See `RewardAlgo::distribute_for_data`.
+    fn distribute_for_data(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+        data_hash: &[u8],
+    ) -> Result<HashMap<AccountId, Money>> {
+        let (shares, leftover) =
+            self.capped_distribution(total_reward, accounts_work, all_work, Some(data_hash))?;
+        fully_distributed(shares, leftover)
+    }
+}
 
-        let mut shares_sum = 0;
-        let mut shares: Vec<(AccountId, u64)> = Default::default();
+/// Cost of, and rewards for, serving data, i.e. bandwidth.
+/// This is a `LinearRewards`, with the cost per byte served given in nanos.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BandwidthRewards {
+    linear: LinearRewards,
+}
 
-        for (id, work) in &accounts_work {
-            let share = (total_reward as f64 / (all_work as f64 / *work as f64)).round() as u64;
-            shares.push((*id, share));
-            shares_sum += share;
+impl BandwidthRewards {
+    /// Passed in is the base cost for buying a unit of work,
+    /// and the cost (in nanos) per byte served.
+    pub fn new(base_cost: Money, multiplier: u64) -> Self {
+        Self {
+            linear: LinearRewards::new(base_cost, Money::from_nano(multiplier)),
         }
+    }
+}
 
-        // Add/remove diff.
-        match total_reward.cmp(&shares_sum) {
-            Ordering::Greater => {
+impl RewardAlgo for BandwidthRewards {
+    /// See `LinearRewards::set`.
+    fn set(&mut self, base_cost: Money) {
+        self.linear.set(base_cost)
+    }
+
+    /// See `LinearRewards::base_cost`.
+    fn base_cost(&self) -> Money {
+        self.linear.base_cost()
+    }
+
+    /// Here, reward units are the
+    /// number of bytes served.
+    fn work_cost(&self, bytes_served: u64) -> Money {
+        self.linear.work_cost(bytes_served)
+    }
+
+    /// See `LinearRewards::total_reward`.
+    fn total_reward(&self, factor: f64, work_cost: Money) -> Result<Money> {
+        self.linear.total_reward(factor, work_cost)
+    }
+
+    /// See `LinearRewards::distribute`.
+    fn distribute(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+    ) -> Result<HashMap<AccountId, Money>> {
+        self.linear.distribute(total_reward, accounts_work)
+    }
+
+    /// See `LinearRewards::distribute_with_total_work`.
+    fn distribute_with_total_work(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+    ) -> Result<HashMap<AccountId, Money>> {
+        self.linear
+            .distribute_with_total_work(total_reward, accounts_work, all_work)
+    }
+
+    /// See `LinearRewards::distribute_for_data`.
+    fn distribute_for_data(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+        data_hash: &[u8],
+    ) -> Result<HashMap<AccountId, Money>> {
+        self.linear
+            .distribute_for_data(total_reward, accounts_work, all_work, data_hash)
+    }
+}
+
+/// Cost of, and rewards for, work with a configurable cost per reward unit,
+/// i.e. `base_cost + units * unit_cost`. With a unit cost of 1 nano,
+/// the cost is the same as with `StorageRewards`.
+/// Rewards are distributed proportionally to work, just as with `StorageRewards`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinearRewards {
+    base_cost: Money,
+    unit_cost: Money,
+}
+
+impl LinearRewards {
+    /// Passed in is the base cost for buying a unit of work,
+    /// and the cost per reward unit.
+    pub fn new(base_cost: Money, unit_cost: Money) -> Self {
+        Self {
+            base_cost,
+            unit_cost,
+        }
+    }
+
+    /// The cost per reward unit.
+    pub fn unit_cost(&self) -> Money {
+        self.unit_cost
+    }
+
+    /// Same as `work_cost`, but returns `FarmingError::Overflow`
+    /// instead of saturating, if the cost does not fit in `Money`.
+    pub fn checked_work_cost(&self, reward_units: u64) -> Result<Money> {
+        reward_units
+            .checked_mul(self.unit_cost.as_nano())
+            .and_then(|cost| cost.checked_add(self.base_cost.as_nano()))
+            .map(Money::from_nano)
+            .ok_or(FarmingError::Overflow)
+    }
+}
+
+impl RewardAlgo for LinearRewards {
+    /// Use this to update the base cost,
+    /// as per any desired formula and frequency.
+    fn set(&mut self, base_cost: Money) {
+        self.base_cost = base_cost;
+    }
+
+    /// The base cost, as last set.
+    fn base_cost(&self) -> Money {
+        self.base_cost
+    }
+
+    /// The unit cost per reward unit + base cost, saturating at the max value.
+    /// See `checked_work_cost`.
+    fn work_cost(&self, reward_units: u64) -> Money {
+        let cost = reward_units
+            .saturating_mul(self.unit_cost.as_nano())
+            .saturating_add(self.base_cost.as_nano());
+        Money::from_nano(cost)
+    }
+
+    /// See `StorageRewards::total_reward`.
+    fn total_reward(&self, factor: f64, work_cost: Money) -> Result<Money> {
+        check_factor(factor, false)?;
+        Ok(scaled_reward(factor, work_cost, RoundingMode::Nearest))
+    }
+
+    /// See `StorageRewards::distribute`.
+    fn distribute(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+    ) -> Result<HashMap<AccountId, Money>> {
+        let all_work = total_work(&accounts_work);
+        self.distribute_with_total_work(total_reward, accounts_work, all_work)
+    }
+
+    /// See `distribute`.
+    fn distribute_with_total_work(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+    ) -> Result<HashMap<AccountId, Money>> {
+        proportional_distribution(total_reward, accounts_work, all_work, None)
+    }
+
+    /// See `RewardAlgo::distribute_for_data`.
+    fn distribute_for_data(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+        all_work: u128,
+        data_hash: &[u8],
+    ) -> Result<HashMap<AccountId, Money>> {
+        proportional_distribution(total_reward, accounts_work, all_work, Some(data_hash))
+    }
+}
+
+/// Cost of, and rewards for, storage, with the cost of a `LinearRewards` with a unit cost
+/// of 1 nano, but with the rewards distributed by a weight of `work^alpha`.
+/// With an `alpha` above 1, the weight grows faster than the work, so that older nodes,
+/// with more work, are rewarded super-linearly. With an `alpha` of 1,
+/// the distribution is proportional to work.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecayRewards {
+    linear: LinearRewards,
+    alpha: f64,
+}
+
+impl DecayRewards {
+    /// Passed in is the base cost for buying a unit of work,
+    /// and the exponent by which work is weighted.
+    /// The `alpha` must be finite and at least 1, or `FarmingError::AlphaOutOfRange` is returned.
+    pub fn new(base_cost: Money, alpha: f64) -> Result<Self> {
+        if !alpha.is_finite() || alpha < 1.0 {
+            return Err(FarmingError::AlphaOutOfRange(alpha));
+        }
+        Ok(Self {
+            linear: LinearRewards::new(base_cost, Money::from_nano(1)),
+            alpha,
+        })
+    }
+
+    /// The exponent by which work is weighted.
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+}
+
+impl RewardAlgo for DecayRewards {
+    /// See `LinearRewards::set`.
+    fn set(&mut self, base_cost: Money) {
+        self.linear.set(base_cost)
+    }
+
+    /// See `LinearRewards::base_cost`.
+    fn base_cost(&self) -> Money {
+        self.linear.base_cost()
+    }
+
+    /// See `LinearRewards::work_cost`.
+    fn work_cost(&self, num_bytes: u64) -> Money {
+        self.linear.work_cost(num_bytes)
+    }
+
+    /// See `LinearRewards::total_reward`.
+    fn total_reward(&self, factor: f64, work_cost: Money) -> Result<Money> {
+        self.linear.total_reward(factor, work_cost)
+    }
+    /// Distributes the reward by the weight of `work^alpha`.
+    /// The work is divided by the largest work before it is raised to `alpha`,
+    /// so that the weights are at most 1, and do not overflow for any work.
+    /// If no account has work, all are weighted the same.
+    /// The shares are rounded down, and the nanos left over go one by one
+    /// to the largest fractional parts, with ties broken by account id,
+    /// so that the shares always sum to exactly the total reward.
+    fn distribute(
+        &self,
+        total_reward: Money,
+        accounts_work: HashMap<AccountId, Work>,
+    ) -> Result<HashMap<AccountId, Money>> {
+        let max_work = accounts_work.values().copied().max().unwrap_or(0);
+        let weights = accounts_work
+            .into_iter()
+            .map(|(id, work)| {
+                let weight = if max_work == 0 {
+                    1.0
+                } else {
+                    (work as f64 / max_work as f64).powf(self.alpha)
+                };
+                (id, weight)
+            })
+            .collect();
+        weighted_distribution(total_reward.as_nano(), weights)
+    }
+}
+
+#[allow(clippy::needless_range_loop)]
+/// Distributes the reward proportionally to the work of the accounts,
+/// and reconciles the rounded shares with the total reward.
+/// See `StorageRewards::distribute`, and `StorageRewards::distribute_for_data`
+/// for how the data hash, if any, is used.
+/// Accounts with the same work get the same share, so shares are computed and
+/// reconciled per group of accounts with the same work, and only expanded to
+/// the accounts at the end; in large sections, most accounts share a few work values.
+fn proportional_distribution(
+    total_reward: Money,
+    accounts_work: HashMap<AccountId, Work>,
+    all_work: u128,
+    data_hash: Option<&[u8]>,
+) -> Result<HashMap<AccountId, Money>> {
+    // A lower total would make shares exceed the total reward.
+    let accounts_total = total_work(&accounts_work);
+    if all_work < accounts_total {
+        return Err(FarmingError::InvalidTotalWork {
+            total_work: all_work,
+            accounts_work: accounts_total,
+        });
+    }
+    let total_reward = total_reward.as_nano();
+    let groups = work_groups(total_reward, &accounts_work, all_work);
+    // Shares are rounded half up, so with a total reward near the max value
+    // they may sum to more than fits in `u64`.
+    let shares_sum: u128 = groups
+        .iter()
+        .map(|group| group.share as u128 * group.ids.len() as u128)
+        .sum();
+    let mut shares: HashMap<AccountId, u64> = groups
+        .iter()
+        .flat_map(|group| group.ids.iter().map(move |id| (*id, group.share)))
+        .collect();
+
+    // Add/remove diff.
+    match (total_reward as u128).cmp(&shares_sum) {
+        Ordering::Greater => {
+            // Less than the total reward, so it fits in `u64`.
+            let remainder = (total_reward as u128 - shares_sum) as u64;
+            if let Some(data_hash) = data_hash {
+                distribute_remainder(&mut shares, remainder, &groups, data_hash);
+            } else if let Some(top) = top_worker(&groups) {
                 // Does not cover probabilistic distribution
                 // (i.e. when total_reward < number of accounts),
                 // since we do not have a shared random value here.
-                // We could put it at the acc closest to the data hash though.. TBD
-                if !shares.is_empty() {
-                    shares.sort_by_key(|t| t.1);
-                    let index = 0; // for now, remainder goes to top worker
-                    let (id, share) = shares[index];
-                    let remainder = total_reward - shares_sum;
-                    let new_share = share + remainder;
-                    shares[index] = (id, new_share);
+                // See `distribute_for_data` and `StorageRewards::distribute_probabilistic`.
+                // The remainder goes to the top worker, i.e. the largest share,
+                // with ties broken by the account id, so that all Elders pick the same account.
+                if let Some(share) = shares.get_mut(&top) {
+                    *share += remainder;
                 }
             }
-            Ordering::Less => {
-                let mut diff = shares_sum - total_reward;
-                shares.sort_by_key(|t| t.1);
-                while diff > 0 {
-                    for i in 0..shares.len() {
-                        let (id, share) = shares[i];
-                        if 0 == diff {
-                            break;
-                        } else if share >= 1 {
-                            shares[i] = (id, share - 1);
-                            diff -= 1;
-                        }
-                    }
+        }
+        Ordering::Less => {
+            let diff = (shares_sum - total_reward as u128) as u64;
+            // Nanos are taken from the smallest shares first, with ties
+            // broken by the account id, so that all Elders pick the same accounts.
+            // Since every share is rounded half up, the diff is less than the number
+            // of shares rounded up, so no share loses more than one nano.
+            for id in smallest_shares(&groups, diff) {
+                if let Some(share) = shares.get_mut(&id) {
+                    *share -= 1;
                 }
             }
-            Ordering::Equal => (),
-        };
-
-        let shares_sum = (&shares).iter().map(|(_, share)| share).sum();
-        if total_reward != shares_sum {
-            panic!("total_reward: {}, shares_sum: {}", total_reward, shares_sum);
         }
+        Ordering::Equal => (),
+    };
 
-        shares
-            .into_iter()
-            .map(|(i, s)| (i, Money::from_nano(s)))
-            .collect()
+    check_shares_sum(total_reward, shares.values())?;
+
+    Ok(shares
+        .into_iter()
+        .map(|(i, s)| (i, Money::from_nano(s)))
+        .collect())
+}
+
+/// Returns `FarmingError::DistributionMismatch` unless the shares sum to the total reward.
+/// The sum is taken in `u128`, so that shares summing to more than `u64::MAX` are caught.
+fn check_shares_sum<'a>(total_reward: u64, shares: impl Iterator<Item = &'a u64>) -> Result<()> {
+    let shares_sum: u128 = shares.map(|share| *share as u128).sum();
+    if total_reward as u128 != shares_sum {
+        return Err(FarmingError::DistributionMismatch {
+            total_reward: Money::from_nano(total_reward),
+            shares_sum: Money::from_nano(shares_sum.min(u64::MAX as u128) as u64),
+        });
     }
+    Ok(())
 }
-#[cfg(test)]
-mod test {
-    use super::*;
-    use safe_nd::{Money, PublicKey, Result};
-    use threshold_crypto::SecretKey;
 
-    fn get_random_pk() -> PublicKey {
-        PublicKey::from(SecretKey::random().public_key())
+/// Returns the shares if nothing was left undistributed by the max share.
+fn fully_distributed(
+    shares: HashMap<AccountId, Money>,
+    leftover: Money,
+) -> Result<HashMap<AccountId, Money>> {
+    if leftover == Money::zero() {
+        Ok(shares)
+    } else {
+        Err(FarmingError::Undistributed(leftover))
     }
+}
 
-    #[test]
-    fn distributes_proportionally() -> Result<()> {
-        // 7 workers, with accumulated work of 1 to 7, shares 7!=28 nanos of reward.
-        // This will result in each worker getting one nano per acc work unit.
-        let calc = StorageRewards::new(Money::from_nano(0));
+/// Gives the remainder one nano at a time to the accounts with work, in the order
+/// of their XOR distance to the data hash, starting over from the closest if there
+/// is more remainder than accounts. If no account has work, all accounts are considered.
+fn distribute_remainder(
+    shares: &mut HashMap<AccountId, u64>,
+    remainder: u64,
+    groups: &[WorkGroup],
+    data_hash: &[u8],
+) {
+    let any_work = groups.iter().any(|group| group.work > 0);
+    let mut candidates: Vec<(Vec<u8>, AccountId)> = groups
+        .iter()
+        .filter(|group| group.work > 0 || !any_work)
+        .flat_map(|group| group.ids.iter())
+        .map(|id| (xor_distance(id, data_hash), *id))
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let count = candidates.len() as u64;
+    let (each, extra) = (remainder / count, (remainder % count) as usize);
+    // Only the accounts getting an extra nano need to be ordered.
+    // The distance is unique per account, so they are the same on all Elders.
+    if extra > 0 {
+        let _ = candidates.select_nth_unstable(extra - 1);
+    }
+    for (rank, (_, id)) in candidates.iter().enumerate() {
+        if let Some(share) = shares.get_mut(id) {
+            *share += each + if rank < extra { 1 } else { 0 };
+        }
+    }
+}
+
+/// Distributes the reward by the weights, which must be finite and not negative,
+/// and reconciles the rounded down shares with the total reward.
+/// See `DecayRewards::distribute`.
+fn weighted_distribution(
+    total_reward: u64,
+    weights: Vec<(AccountId, f64)>,
+) -> std::result::Result<HashMap<AccountId, Money>, FarmingError> {
+    let weight_sum: f64 = weights.iter().map(|(_, weight)| weight).sum();
+    let mut shares: Vec<(AccountId, u64, f64)> = weights
+        .into_iter()
+        .map(|(id, weight)| {
+            let exact = total_reward as f64 * (weight / weight_sum);
+            let share = exact.floor().min(total_reward as f64);
+            (id, share as u64, exact - share)
+        })
+        .collect();
+    // Largest fractional parts first, ordered the same way on all Elders.
+    shares.sort_by(|a, b| {
+        b.2.partial_cmp(&a.2)
+            .unwrap_or(Ordering::Equal)
+            .then(a.0.cmp(&b.0))
+    });
+
+    let shares_sum: u128 = shares.iter().map(|(_, share, _)| *share as u128).sum();
+    if !shares.is_empty() {
+        // Floats may leave the sum off by more than the number of accounts
+        // for very large rewards, so the difference is spread over as many passes as needed.
+        let (len, mut index) = (shares.len(), 0);
+        let mut diff = total_reward as i128 - shares_sum as i128;
+        while diff > 0 {
+            shares[index].1 += 1;
+            diff -= 1;
+            index = (index + 1) % len;
+        }
+        while diff < 0 {
+            // Smallest fractional parts first.
+            let (_, share, _) = &mut shares[len - 1 - index];
+            if *share > 0 {
+                *share -= 1;
+                diff += 1;
+            }
+            index = (index + 1) % len;
+        }
+    }
+
+    check_shares_sum(total_reward, shares.iter().map(|(_, share, _)| share))?;
+
+    Ok(shares
+        .into_iter()
+        .map(|(id, share, _)| (id, Money::from_nano(share)))
+        .collect())
+}
+
+/// Returns `FarmingError::FactorOutOfRange` unless the factor is finite and greater than 0,
+/// or 0 when that is allowed.
+fn check_factor(factor: f64, allow_zero: bool) -> Result<()> {
+    let valid = factor.is_finite() && (factor > 0.0 || (allow_zero && factor == 0.0));
+    if !valid {
+        return Err(FarmingError::FactorOutOfRange(factor));
+    }
+    Ok(())
+}
+
+/// The largest distance, in nanos, from a whole nano at which
+/// a scaled reward is taken to be that nano, see `scaled_reward`.
+const MAX_SNAP_TOLERANCE: f64 = 1e-6;
+
+/// The work cost scaled by the factor, rounded to whole nanos as per the mode.
+/// A product within a few ulps of a whole nano is taken to be that nano,
+/// since e.g. `0.29 * 100.0` is `28.999999999999996`, which would otherwise floor to 28.
+/// The tolerance is also bounded absolutely, as a few ulps of a large amount
+/// would span most of a nano, and floor and ceil would then round to nearest.
+fn scaled_reward(factor: f64, work_cost: Money, rounding_mode: RoundingMode) -> Money {
+    let amount = factor * work_cost.as_nano() as f64;
+    let nearest = amount.round();
+    let tolerance = (16.0 * f64::EPSILON * amount.abs().max(1.0)).min(MAX_SNAP_TOLERANCE);
+    let amount = if (amount - nearest).abs() <= tolerance {
+        nearest
+    } else {
+        match rounding_mode {
+            RoundingMode::Floor => amount.floor(),
+            RoundingMode::Ceil => amount.ceil(),
+            RoundingMode::Nearest => nearest,
+        }
+    };
+    Money::from_nano(amount as u64)
+}
+
+/// Accounts with the same work, and so the same proportional share,
+/// before it is reconciled with the total reward.
+struct WorkGroup {
+    work: Work,
+    share: u64,
+    ids: Vec<AccountId>,
+}
+
+/// The accounts grouped by work, in ascending order of work, and so of share.
+fn work_groups(
+    total_reward: u64,
+    accounts_work: &HashMap<AccountId, Work>,
+    all_work: u128,
+) -> Vec<WorkGroup> {
+    let mut by_work: BTreeMap<Work, Vec<AccountId>> = BTreeMap::new();
+    for (id, work) in accounts_work {
+        by_work.entry(*work).or_default().push(*id);
+    }
+    by_work
+        .into_iter()
+        .map(|(work, ids)| WorkGroup {
+            work,
+            share: proportional_share(total_reward, work, all_work),
+            ids,
+        })
+        .collect()
+}
+
+/// The account with the largest share, with ties broken by the highest account id.
+fn top_worker(groups: &[WorkGroup]) -> Option<AccountId> {
+    let top_share = groups.last()?.share;
+    groups
+        .iter()
+        .rev()
+        .take_while(|group| group.share == top_share)
+        .flat_map(|group| group.ids.iter())
+        .max()
+        .copied()
+}
+
+/// The first `count` accounts with a share of at least one nano, in ascending
+/// order of share, with ties broken by the account id. Only the accounts
+/// with the last share needed are sorted.
+fn smallest_shares(groups: &[WorkGroup], count: u64) -> Vec<AccountId> {
+    let mut selected = vec![];
+    let mut remaining = count as usize;
+    let mut groups = groups.iter().filter(|group| group.share > 0).peekable();
+    while remaining > 0 {
+        let share = match groups.peek() {
+            Some(group) => group.share,
+            None => break,
+        };
+        let mut tied: Vec<AccountId> = vec![];
+        while let Some(group) = groups.next_if(|group| group.share == share) {
+            tied.extend(&group.ids);
+        }
+        if tied.len() > remaining {
+            tied.sort_unstable();
+            tied.truncate(remaining);
+        }
+        remaining -= tied.len();
+        selected.extend(tied);
+    }
+    selected
+}
+
+/// `total_reward * work / all_work`, rounded half up, in exact integer math.
+/// The product fits in `u128`, and the share fits in `u64` since `work <= all_work`.
+fn proportional_share(total_reward: u64, work: Work, all_work: u128) -> u64 {
+    if all_work == 0 {
+        return 0;
+    }
+    let product = total_reward as u128 * work as u128;
+    let (quotient, remainder) = (product / all_work, product % all_work);
+    let rounded = if remainder >= all_work - remainder {
+        quotient + 1
+    } else {
+        quotient
+    };
+    rounded as u64
+}
+
+/// A number below `bound`, drawn from the SHA3-256 hash of the seed and the round,
+/// so that it is the same on all platforms.
+fn seeded_draw(seed: &[u8; 32], round: u64, bound: u128) -> u128 {
+    let mut hasher = Sha3::v256();
+    hasher.update(seed);
+    hasher.update(&round.to_le_bytes());
+    let mut digest = [0; 32];
+    hasher.finalize(&mut digest);
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    u128::from_le_bytes(bytes) % bound
+}
+
+/// `total_reward * work / all_work`, rounded down, in exact integer math.
+fn floored_share(total_reward: u64, work: Work, all_work: u128) -> u64 {
+    if all_work == 0 {
+        return 0;
+    }
+    (total_reward as u128 * work as u128 / all_work) as u64
+}
+
+/// The sum of the work of all accounts.
+fn total_work(accounts_work: &HashMap<AccountId, Work>) -> u128 {
+    accounts_work.values().map(|work| *work as u128).sum()
+}
+
+/// Computes the total reward for storing `num_bytes`, from recorded parameters,
+/// without needing the reward algo instance that produced it.
+/// The work cost is `base_cost + num_bytes * unit_cost`, which is scaled by the factor
+/// and rounded as per the mode, i.e. `StorageRewards` corresponds to a `unit_cost` of 1 nano.
+/// The factor is validated as by `StorageRewards::total_reward` with zero factors disallowed,
+/// so `FarmingError::FactorOutOfRange` is returned unless it is finite and greater than 0.
+/// Returns `FarmingError::Overflow` if the work cost overflows.
+pub fn total_reward_for(
+    base_cost: Money,
+    unit_cost: Money,
+    num_bytes: u64,
+    factor: f64,
+    rounding_mode: RoundingMode,
+) -> Result<Money> {
+    check_factor(factor, false)?;
+    let work_cost = unit_cost
+        .as_nano()
+        .checked_mul(num_bytes)
+        .and_then(|cost| cost.checked_add(base_cost.as_nano()))
+        .ok_or(FarmingError::Overflow)?;
+    Ok(scaled_reward(
+        factor,
+        Money::from_nano(work_cost),
+        rounding_mode,
+    ))
+}
+
+/// Merges the results of several reward algos for one and the same rewarded event,
+/// where each part is a total reward and its distribution.
+/// Returns the summed total and the merged per-account shares.
+/// Returns `FarmingError::InvalidDistribution` if any part's distribution doesn't sum to
+/// its total, and `FarmingError::Overflow` on overflow.
+pub fn combine_rewards(
+    parts: Vec<(Money, HashMap<AccountId, Money>)>,
+) -> Result<(Money, HashMap<AccountId, Money>)> {
+    let mut total = Money::zero();
+    let mut combined: HashMap<AccountId, Money> = HashMap::new();
+    for (part_total, distribution) in parts {
+        let mut part_sum = Money::zero();
+        for (id, share) in distribution {
+            part_sum = part_sum.checked_add(share).ok_or(FarmingError::Overflow)?;
+            let existing = combined.get(&id).copied().unwrap_or_else(Money::zero);
+            let merged = existing.checked_add(share).ok_or(FarmingError::Overflow)?;
+            let _ = combined.insert(id, merged);
+        }
+        if part_sum != part_total {
+            return Err(FarmingError::InvalidDistribution);
+        }
+        total = total
+            .checked_add(part_total)
+            .ok_or(FarmingError::Overflow)?;
+    }
+    Ok((total, combined))
+}
+
+/// Statistics over the shares of a distribution, in nanos.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShareStats {
+    /// The smallest share.
+    pub min: Money,
+    /// The largest share.
+    pub max: Money,
+    /// The mean share.
+    pub mean: f64,
+    /// The (population) variance of the shares.
+    pub variance: f64,
+}
+
+/// Returns min, max, mean and variance of the shares in a distribution,
+/// e.g. to detect unusually skewed reward rounds.
+/// All zero for an empty distribution.
+pub fn share_stats(distribution: &HashMap<AccountId, Money>) -> ShareStats {
+    let shares: Vec<u64> = distribution.values().map(|s| s.as_nano()).collect();
+    if shares.is_empty() {
+        return ShareStats::default();
+    }
+    let count = shares.len() as f64;
+    let sum: u128 = shares.iter().map(|s| *s as u128).sum();
+    let mean = sum as f64 / count;
+    let variance = shares
+        .iter()
+        .map(|s| (*s as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    ShareStats {
+        min: Money::from_nano(*shares.iter().min().unwrap_or(&0)),
+        max: Money::from_nano(*shares.iter().max().unwrap_or(&0)),
+        mean,
+        variance,
+    }
+}
+
+/// Returns whether exactly one account has a nonzero share,
+/// e.g. because only one account had any work.
+/// Such a distribution could indicate misconfiguration.
+pub fn is_single_recipient(distribution: &HashMap<AccountId, Money>) -> bool {
+    distribution
+        .values()
+        .filter(|share| share.as_nano() > 0)
+        .count()
+        == 1
+}
+
+/// Validates that no account without work is paid by the distribution,
+/// as accounts with zero work are not to be rewarded.
+/// Accounts missing from `accounts_work` are considered to have zero work.
+/// Returns `FarmingError::InvalidDistribution` if any such account has a nonzero share.
+pub fn validate_no_zero_work_reward(
+    distribution: &HashMap<AccountId, Money>,
+    accounts_work: &HashMap<AccountId, Work>,
+) -> Result<()> {
+    let pays_idle = distribution
+        .iter()
+        .any(|(id, share)| share.as_nano() > 0 && accounts_work.get(id).copied().unwrap_or(0) == 0);
+    if pays_idle {
+        return Err(FarmingError::InvalidDistribution);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use safe_nd::{Money, PublicKey};
+    use threshold_crypto::SecretKey;
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+
+    #[test]
+    fn distributes_proportionally() -> Result<()> {
+        // 7 workers, with accumulated work of 1 to 7, shares 7!=28 nanos of reward.
+        // This will result in each worker getting one nano per acc work unit.
+        let calc = StorageRewards::new(Money::from_nano(0));
         let accounts_work = (1..8).map(|i| (get_random_pk(), i)).collect();
         let mut dist: Vec<Money> = calc
-            .distribute(Money::from_nano(28), accounts_work)
+            .distribute(Money::from_nano(28), accounts_work)?
             .into_iter()
             .map(|(_, reward)| reward)
             .collect();
@@ -201,4 +1404,948 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn integer_shares_sum_exactly_without_drift() -> Result<()> {
+        // Large work values, where f64 can not represent the products exactly.
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = (1..=50)
+            .map(|i| (get_random_pk(), (1 << 53) + i * 7_919))
+            .collect();
+        let all_work = total_work(&accounts_work);
+        let total_reward = Money::from_nano(999_999_999_999_999_989);
+
+        let dist = calc.distribute(total_reward, accounts_work.clone())?;
+
+        let sum: u64 = dist.values().map(|share| share.as_nano()).sum();
+        assert_eq!(sum, total_reward.as_nano());
+        let mut float_drift = 0.0_f64;
+        for (id, share) in &dist {
+            let work = accounts_work[id];
+            let exact = total_reward.as_nano() as u128 * work as u128;
+            // At most one nano off the exact share, from the reconciliation ..
+            let share = share.as_nano() as u128 * all_work;
+            assert!(share.max(exact) - share.min(exact) <= all_work);
+            let float = (total_reward.as_nano() as f64 / (all_work as f64 / work as f64)).round();
+            float_drift = float_drift.max((float - (exact / all_work) as f64).abs());
+        }
+        // .. where the float version drifts by more.
+        assert!(float_drift > 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn shares_of_the_max_reward_sum_without_overflow() -> Result<()> {
+        // Both shares round up to 2^63, which sum to more than `u64::MAX`.
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = (0..2).map(|_| (get_random_pk(), 1)).collect();
+        let total_reward = Money::from_nano(u64::MAX);
+
+        let dist = calc.distribute(total_reward, accounts_work.clone())?;
+        let for_data =
+            calc.distribute_for_data(total_reward, accounts_work.clone(), 2, &[7; 32])?;
+
+        for dist in &[dist, for_data] {
+            let sum: u128 = dist.values().map(|share| share.as_nano() as u128).sum();
+            assert_eq!(sum, u64::MAX as u128);
+            let mut shares: Vec<_> = dist.values().map(|share| share.as_nano()).collect();
+            shares.sort_unstable();
+            assert_eq!(shares, vec![(1 << 63) - 1, 1 << 63]);
+        }
+        assert_eq!(
+            calc.reconciliation_iterations(total_reward, &accounts_work),
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shares_over_the_cap_are_redistributed() -> Result<()> {
+        let mut calc = StorageRewards::new(Money::from_nano(0));
+        calc.set_max_share(Some(Money::from_nano(40)));
+        let (senior, mid, junior) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let accounts_work = vec![(senior, 8), (mid, 1), (junior, 1)]
+            .into_iter()
+            .collect();
+
+        // The senior would get 80, where the cap is 40.
+        let (dist, leftover) = calc.distribute_capped(Money::from_nano(100), accounts_work)?;
+
+        assert_eq!(leftover, Money::zero());
+        assert_eq!(dist[&senior], Money::from_nano(40));
+        assert_eq!(dist[&mid], Money::from_nano(30));
+        assert_eq!(dist[&junior], Money::from_nano(30));
+        Ok(())
+    }
+
+    #[test]
+    fn when_all_accounts_are_capped_the_leftover_is_returned() -> Result<()> {
+        let mut calc = StorageRewards::new(Money::from_nano(0));
+        calc.set_max_share(Some(Money::from_nano(10)));
+        let accounts_work: HashMap<_, _> = (1..4).map(|i| (get_random_pk(), i)).collect();
+
+        let (dist, leftover) =
+            calc.distribute_capped(Money::from_nano(100), accounts_work.clone())?;
+
+        assert!(dist.values().all(|share| *share == Money::from_nano(10)));
+        assert_eq!(leftover, Money::from_nano(70));
+        assert_eq!(
+            calc.distribute(Money::from_nano(100), accounts_work),
+            Err(FarmingError::Undistributed(leftover))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn base_cost_reads_back_what_was_set() {
+        let mut calc = StorageRewards::new(Money::from_nano(7));
+        assert_eq!(calc.base_cost(), Money::from_nano(7));
+        calc.set(Money::from_nano(11));
+        assert_eq!(calc.base_cost(), Money::from_nano(11));
+    }
+
+    #[test]
+    fn rounding_modes_round_fractional_rewards_differently() {
+        // Scales a work cost of 10 nanos, to 13.3 and 13.6 nanos.
+        let total_rewards = |mode| {
+            let calc = StorageRewards::with_rounding_mode(Money::from_nano(0), mode);
+            let work_cost = calc.work_cost(10);
+            (
+                calc.total_reward(1.33, work_cost).unwrap().as_nano(),
+                calc.total_reward(1.36, work_cost).unwrap().as_nano(),
+            )
+        };
+        assert_eq!(total_rewards(RoundingMode::Floor), (13, 13));
+        assert_eq!(total_rewards(RoundingMode::Ceil), (14, 14));
+        assert_eq!(total_rewards(RoundingMode::Nearest), (13, 14));
+        let calc = StorageRewards::new(Money::from_nano(0));
+        assert_eq!(calc.rounding_mode(), RoundingMode::Nearest);
+    }
+
+    #[test]
+    fn whole_rewards_are_not_rounded_away_by_float_error() -> Result<()> {
+        let work_cost = Money::from_nano(100);
+        let floor = StorageRewards::with_rounding_mode(Money::from_nano(0), RoundingMode::Floor);
+        let ceil = StorageRewards::with_rounding_mode(Money::from_nano(0), RoundingMode::Ceil);
+
+        // 0.29 * 100 is just below 29, and 0.07 * 100 just above 7.
+        assert_eq!(floor.total_reward(0.29, work_cost)?, Money::from_nano(29));
+        assert_eq!(ceil.total_reward(0.07, work_cost)?, Money::from_nano(7));
+        // Fractions well off a whole nano are still rounded as per the mode.
+        assert_eq!(floor.total_reward(0.295, work_cost)?, Money::from_nano(29));
+        assert_eq!(ceil.total_reward(0.075, work_cost)?, Money::from_nano(8));
+        Ok(())
+    }
+
+    #[test]
+    fn large_rewards_are_rounded_as_per_the_mode() -> Result<()> {
+        // Scales to 500_000_000_000_000.5 nanos, which is exact in f64,
+        // but within a few ulps of both neighbouring nanos.
+        let work_cost = Money::from_nano(1_000_000_000_000_001);
+        let total_reward = |mode| {
+            StorageRewards::with_rounding_mode(Money::from_nano(0), mode)
+                .total_reward(0.5, work_cost)
+                .map(|reward| reward.as_nano())
+        };
+        assert_eq!(total_reward(RoundingMode::Floor)?, 500_000_000_000_000);
+        assert_eq!(total_reward(RoundingMode::Ceil)?, 500_000_000_000_001);
+        assert_eq!(total_reward(RoundingMode::Nearest)?, 500_000_000_000_001);
+        Ok(())
+    }
+
+    #[test]
+    fn dust_shares_are_dropped_and_redistributed() -> Result<()> {
+        let mut calc = StorageRewards::new(Money::from_nano(0));
+        calc.set_min_reward(Money::from_nano(10));
+        let (senior, mid, junior, idle) = (
+            get_random_pk(),
+            get_random_pk(),
+            get_random_pk(),
+            get_random_pk(),
+        );
+        let accounts_work = vec![(senior, 60), (mid, 35), (junior, 5), (idle, 0)]
+            .into_iter()
+            .collect();
+
+        // The junior would get 5 nanos, and the idle account nothing.
+        let dist = calc.distribute(Money::from_nano(100), accounts_work)?;
+
+        assert_eq!(dist.len(), 2);
+        let sum: u64 = dist.values().map(|share| share.as_nano()).sum();
+        assert_eq!(sum, 100);
+        assert!(dist[&senior] >= Money::from_nano(63));
+        assert!(dist[&mid] >= Money::from_nano(36));
+
+        // When nobody reaches the threshold, nobody is dropped.
+        let accounts_work: HashMap<_, _> = (1..4).map(|i| (get_random_pk(), i)).collect();
+        assert_eq!(
+            calc.distribute(Money::from_nano(6), accounts_work)?.len(),
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn zero_factor_is_rejected_by_default() {
+        let calc = StorageRewards::new(Money::from_nano(2));
+        let work_cost = calc.work_cost(3);
+        assert_eq!(
+            calc.total_reward(0.0, work_cost),
+            Err(FarmingError::FactorOutOfRange(0.0))
+        );
+        assert_eq!(calc.total_reward(2.0, work_cost), Ok(Money::from_nano(10)));
+    }
+
+    #[test]
+    fn non_finite_and_non_positive_factors_are_rejected() {
+        let storage = StorageRewards::new(Money::from_nano(2));
+        let bandwidth = BandwidthRewards::new(Money::from_nano(2), 3);
+        let work_cost = Money::from_nano(5);
+        for factor in &[f64::NAN, 0.0, -1.0, f64::INFINITY] {
+            assert!(matches!(
+                storage.total_reward(*factor, work_cost),
+                Err(FarmingError::FactorOutOfRange(_))
+            ));
+            assert!(matches!(
+                bandwidth.total_reward(*factor, work_cost),
+                Err(FarmingError::FactorOutOfRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn zero_factor_is_accepted_when_allowed() -> Result<()> {
+        let mut calc = StorageRewards::new(Money::from_nano(2));
+        calc.allow_zero_factor(true);
+        let work_cost = calc.work_cost(3);
+        assert_eq!(calc.total_reward(0.0, work_cost)?, Money::zero());
+        assert_eq!(
+            calc.total_reward(-1.0, work_cost),
+            Err(FarmingError::FactorOutOfRange(-1.0))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn share_deviation_is_within_rounding() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = [3, 7, 11, 13, 1]
+            .iter()
+            .map(|work| (get_random_pk(), *work))
+            .collect();
+        let total_reward = Money::from_nano(1000);
+        let distribution = calc.distribute(total_reward, accounts_work.clone())?;
+
+        let deviation = calc.share_deviation(&distribution, total_reward, &accounts_work);
+
+        assert_eq!(deviation.len(), accounts_work.len());
+        assert!(deviation.values().all(|d| d.abs() <= 1));
+        Ok(())
+    }
+
+    #[test]
+    fn distributes_when_all_work_exceeds_u64() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = (0..3).map(|_| (get_random_pk(), u64::MAX)).collect();
+
+        let dist = calc.distribute(Money::from_nano(30), accounts_work)?;
+
+        assert_eq!(dist.len(), 3);
+        assert!(dist.values().all(|share| *share == Money::from_nano(10)));
+        Ok(())
+    }
+
+    #[test]
+    fn total_work_below_the_accounts_work_is_rejected() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = vec![(get_random_pk(), 3), (get_random_pk(), 7)]
+            .into_iter()
+            .collect();
+        let total_reward = Money::from_nano(100);
+
+        assert_eq!(
+            calc.distribute_with_total_work(total_reward, accounts_work.clone(), 9),
+            Err(FarmingError::InvalidTotalWork {
+                total_work: 9,
+                accounts_work: 10
+            })
+        );
+        let dist = calc.distribute_with_total_work(total_reward, accounts_work, 10)?;
+        assert_eq!(dist.values().map(|share| share.as_nano()).sum::<u64>(), 100);
+        Ok(())
+    }
+
+    #[test]
+    fn effective_factor_is_within_rounding_of_requested() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(2));
+        for (num_bytes, factor) in &[(1, 1.3), (7, 0.71), (1000, 2.345), (3, 1.0)] {
+            let work_cost = calc.work_cost(*num_bytes).as_nano() as f64;
+            let effective = calc.effective_factor(*num_bytes, *factor)?;
+            assert!((effective - factor).abs() <= 0.5 / work_cost);
+        }
+        assert!((calc.effective_factor(3, 1.0)? - 1.0).abs() < f64::EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn combines_consistent_parts() -> Result<()> {
+        let shared = get_random_pk();
+        let other = get_random_pk();
+        let first: HashMap<_, _> =
+            vec![(shared, Money::from_nano(3)), (other, Money::from_nano(7))]
+                .into_iter()
+                .collect();
+        let second: HashMap<_, _> = vec![(shared, Money::from_nano(5))].into_iter().collect();
+
+        let (total, combined) = combine_rewards(vec![
+            (Money::from_nano(10), first),
+            (Money::from_nano(5), second),
+        ])?;
+
+        assert_eq!(total, Money::from_nano(15));
+        assert_eq!(combined.get(&shared), Some(&Money::from_nano(8)));
+        assert_eq!(combined.get(&other), Some(&Money::from_nano(7)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_inconsistent_parts() {
+        let first: HashMap<_, _> = vec![(get_random_pk(), Money::from_nano(3))]
+            .into_iter()
+            .collect();
+
+        let result = combine_rewards(vec![(Money::from_nano(4), first)]);
+
+        assert_eq!(result, Err(FarmingError::InvalidDistribution));
+    }
+
+    #[test]
+    fn share_stats_of_distribution_with_outliers() {
+        let distribution: HashMap<_, _> = [10, 10, 10, 10, 1, 19]
+            .iter()
+            .map(|share| (get_random_pk(), Money::from_nano(*share)))
+            .collect();
+
+        let stats = share_stats(&distribution);
+
+        assert_eq!(stats.min, Money::from_nano(1));
+        assert_eq!(stats.max, Money::from_nano(19));
+        assert!((stats.mean - 10.0).abs() < f64::EPSILON);
+        assert!((stats.variance - 27.0).abs() < f64::EPSILON);
+        assert_eq!(share_stats(&HashMap::new()), ShareStats::default());
+    }
+
+    #[test]
+    fn total_reward_for_matches_storage_rewards() {
+        let modes = [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ];
+        for mode in &modes {
+            for (base_cost, num_bytes, factor) in &[
+                (0, 1, 1.0),
+                (2, 3, 2.0),
+                (17, 12_345, 0.67),
+                (5, 999, 2.7),
+                (0, 10, 1.33),
+                (0, 100, 0.29),
+            ] {
+                let base_cost = Money::from_nano(*base_cost);
+                let calc = StorageRewards::with_rounding_mode(base_cost, *mode);
+                let expected = calc.total_reward(*factor, calc.work_cost(*num_bytes));
+                let actual =
+                    total_reward_for(base_cost, Money::from_nano(1), *num_bytes, *factor, *mode);
+                assert_eq!(expected, actual);
+            }
+            for factor in &[f64::NAN, f64::INFINITY, 0.0, -1.0] {
+                let calc = StorageRewards::with_rounding_mode(Money::from_nano(2), *mode);
+                let expected = calc.total_reward(*factor, calc.work_cost(3));
+                let actual =
+                    total_reward_for(Money::from_nano(2), Money::from_nano(1), 3, *factor, *mode);
+                assert!(matches!(expected, Err(FarmingError::FactorOutOfRange(_))));
+                assert!(matches!(actual, Err(FarmingError::FactorOutOfRange(_))));
+            }
+        }
+        assert_eq!(
+            total_reward_for(
+                Money::from_nano(1),
+                Money::from_nano(2),
+                u64::MAX,
+                1.0,
+                RoundingMode::Nearest
+            ),
+            Err(FarmingError::Overflow)
+        );
+    }
+
+    #[test]
+    fn reconciliation_iterations_of_high_diff_input() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        // Every share is 0.5, rounded up to 1, so half of them must be taken back.
+        let accounts_work: HashMap<_, _> = (0..10).map(|_| (get_random_pk(), 1)).collect();
+        assert_eq!(
+            calc.reconciliation_iterations(Money::from_nano(5), &accounts_work),
+            1
+        );
+        let dist = calc.distribute(Money::from_nano(5), accounts_work.clone())?;
+        assert_eq!(dist.values().map(|s| s.as_nano()).sum::<u64>(), 5);
+        // Exact shares need no reconciliation.
+        assert_eq!(
+            calc.reconciliation_iterations(Money::from_nano(30), &accounts_work),
+            0
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn neutral_factor_reproduces_work_cost() {
+        let calc = StorageRewards::new(Money::from_nano(7));
+        let factor = calc.neutral_factor();
+        let batch = [1, 3_000, 12_345, 1_000_000];
+
+        let total_cost: u64 = batch.iter().map(|b| calc.work_cost(*b).as_nano()).sum();
+        let total_reward: u64 = batch
+            .iter()
+            .map(|b| {
+                calc.total_reward(factor, calc.work_cost(*b))
+                    .unwrap()
+                    .as_nano()
+            })
+            .sum();
+
+        assert_eq!(total_reward, total_cost);
+    }
+
+    #[test]
+    fn single_nonzero_work_account_is_single_recipient() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let worker = get_random_pk();
+        let mut accounts_work: HashMap<_, _> =
+            vec![(worker, 10), (get_random_pk(), 0), (get_random_pk(), 0)]
+                .into_iter()
+                .collect();
+
+        let dist = calc.distribute(Money::from_nano(100), accounts_work.clone())?;
+        assert!(is_single_recipient(&dist));
+        assert_eq!(dist[&worker], Money::from_nano(100));
+
+        let _ = accounts_work.insert(get_random_pk(), 10);
+        let dist = calc.distribute(Money::from_nano(100), accounts_work)?;
+        assert!(!is_single_recipient(&dist));
+        Ok(())
+    }
+
+    #[test]
+    fn min_nonzero_factor_is_the_zero_pay_boundary() {
+        let modes = [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ];
+        for mode in modes.iter().copied() {
+            let calc = StorageRewards::with_rounding_mode(Money::from_nano(3), mode);
+            for num_bytes in [0, 1, 7, 1_000, 123_456_789].iter().copied() {
+                let work_cost = calc.work_cost(num_bytes);
+                let factor = calc.min_nonzero_factor(num_bytes);
+                let below = f64::from_bits(factor.to_bits() - 1);
+
+                let pays = |factor| {
+                    let reward = calc.total_reward(factor, work_cost);
+                    reward.map(|r| r.as_nano() > 0).unwrap_or(false)
+                };
+                assert!(!pays(below));
+                assert!(pays(factor));
+                assert!(pays(factor * 2.0));
+            }
+        }
+        let free = StorageRewards::new(Money::zero());
+        assert_eq!(free.min_nonzero_factor(0), f64::INFINITY);
+    }
+
+    #[test]
+    fn exclusion_cost_is_what_the_others_gain() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let total_reward = Money::from_nano(1_000);
+        let excluded = get_random_pk();
+        let mut accounts_work: HashMap<_, _> = (1..5).map(|work| (get_random_pk(), work)).collect();
+        let _ = accounts_work.insert(excluded, 3);
+
+        let cost = calc.exclusion_cost(total_reward, &accounts_work, &excluded)?;
+
+        let inclusive = calc.distribute(total_reward, accounts_work.clone())?;
+        let _ = accounts_work.remove(&excluded);
+        let exclusive = calc.distribute(total_reward, accounts_work.clone())?;
+        let gained: u64 = exclusive
+            .iter()
+            .map(|(id, share)| share.as_nano() - inclusive[id].as_nano())
+            .sum();
+        assert_eq!(cost, inclusive[&excluded]);
+        assert_eq!(cost.as_nano(), gained);
+        assert_eq!(
+            calc.exclusion_cost(total_reward, &accounts_work, &excluded)?,
+            Money::zero()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_deposit_parts_sum_to_work_cost() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(100));
+        let num_bytes = 900;
+        let work_cost = calc.work_cost(num_bytes);
+
+        let (reward, refund) = calc.split_deposit(num_bytes, 0.25)?;
+        assert_eq!(reward, Money::from_nano(250));
+        assert_eq!(refund, Money::from_nano(750));
+
+        let (reward, refund) = calc.split_deposit(num_bytes, 1.0)?;
+        assert_eq!(reward, work_cost);
+        assert_eq!(refund, Money::zero());
+
+        let (reward, refund) = calc.split_deposit(num_bytes, 3.0)?;
+        assert_eq!(reward, work_cost);
+        assert_eq!(refund, Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn zero_work_reward_is_rejected() {
+        let (worker, idle, unknown) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let accounts_work: HashMap<_, _> = vec![(worker, 5), (idle, 0)].into_iter().collect();
+        let share = |id, amount| (id, Money::from_nano(amount));
+
+        let valid = vec![share(worker, 10), share(idle, 0)]
+            .into_iter()
+            .collect();
+        assert_eq!(validate_no_zero_work_reward(&valid, &accounts_work), Ok(()));
+
+        let pays_idle = vec![share(worker, 9), share(idle, 1)].into_iter().collect();
+        assert_eq!(
+            validate_no_zero_work_reward(&pays_idle, &accounts_work),
+            Err(FarmingError::InvalidDistribution)
+        );
+
+        let pays_unknown = vec![share(worker, 9), share(unknown, 1)]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            validate_no_zero_work_reward(&pays_unknown, &accounts_work),
+            Err(FarmingError::InvalidDistribution)
+        );
+    }
+
+    #[test]
+    fn bandwidth_cost_scales_with_multiplier() -> Result<()> {
+        let base_cost = Money::from_nano(10);
+        let storage = StorageRewards::new(base_cost);
+        let unit = BandwidthRewards::new(base_cost, 1);
+        let bandwidth = BandwidthRewards::new(base_cost, 3);
+        let bytes = 1_000;
+
+        assert_eq!(unit.work_cost(bytes), storage.work_cost(bytes));
+        assert_eq!(bandwidth.work_cost(bytes), Money::from_nano(3_010));
+        assert_ne!(bandwidth.work_cost(bytes), storage.work_cost(bytes));
+        assert_eq!(bandwidth.work_cost(u64::MAX), Money::from_nano(u64::MAX));
+
+        let accounts_work: HashMap<_, _> = (1..4).map(|work| (get_random_pk(), work)).collect();
+        assert_eq!(
+            bandwidth.distribute(Money::from_nano(600), accounts_work.clone())?,
+            storage.distribute(Money::from_nano(600), accounts_work)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn linear_cost_curve_follows_unit_cost() -> Result<()> {
+        let base_cost = Money::from_nano(10);
+        let storage = StorageRewards::new(base_cost);
+        let unit = LinearRewards::new(base_cost, Money::from_nano(1));
+        let linear = LinearRewards::new(base_cost, Money::from_nano(5));
+
+        for units in &[0, 1, 7, 1_000, 123_456] {
+            assert_eq!(unit.work_cost(*units), storage.work_cost(*units));
+            assert_eq!(linear.work_cost(*units), Money::from_nano(10 + 5 * *units));
+            assert_eq!(linear.checked_work_cost(*units)?, linear.work_cost(*units));
+        }
+        // The marginal cost per unit is the unit cost.
+        assert_eq!(
+            linear.work_cost(101).as_nano() - linear.work_cost(100).as_nano(),
+            linear.unit_cost().as_nano()
+        );
+        assert_eq!(linear.work_cost(u64::MAX), Money::from_nano(u64::MAX));
+        assert_eq!(
+            linear.checked_work_cost(u64::MAX),
+            Err(FarmingError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn remainder_goes_to_the_same_account_regardless_of_order() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let mut ids: Vec<_> = (0..5).map(|_| get_random_pk()).collect();
+        ids.sort();
+        // Every share is 2.4, rounded to 2, leaving a remainder of 2 nanos.
+        let total_reward = Money::from_nano(12);
+
+        let mut distributions = vec![];
+        for rotation in 0..ids.len() {
+            let mut ordered = ids.clone();
+            ordered.rotate_left(rotation);
+            let accounts_work: HashMap<_, _> = ordered.into_iter().map(|id| (id, 3)).collect();
+            distributions.push(calc.distribute(total_reward, accounts_work)?);
+        }
+
+        assert!(distributions.iter().all(|dist| *dist == distributions[0]));
+        let top = ids[ids.len() - 1];
+        assert_eq!(distributions[0][&top], Money::from_nano(4));
+        assert!(ids[..4]
+            .iter()
+            .all(|id| distributions[0][id] == Money::from_nano(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn excess_is_taken_from_the_same_account_regardless_of_order() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let mut ids: Vec<_> = (0..3).map(|_| get_random_pk()).collect();
+        ids.sort();
+        // Every share is 0.67, rounded to 1, exceeding the total by 1 nano.
+        let total_reward = Money::from_nano(2);
+
+        let mut distributions = vec![];
+        for rotation in 0..ids.len() {
+            let mut ordered = ids.clone();
+            ordered.rotate_left(rotation);
+            let accounts_work: HashMap<_, _> = ordered.into_iter().map(|id| (id, 1)).collect();
+            distributions.push(calc.distribute(total_reward, accounts_work)?);
+        }
+
+        assert!(distributions.iter().all(|dist| *dist == distributions[0]));
+        assert_eq!(distributions[0][&ids[0]], Money::zero());
+        assert!(ids[1..]
+            .iter()
+            .all(|id| distributions[0][id] == Money::from_nano(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn decay_rewards_favour_the_highest_work() -> Result<()> {
+        let base_cost = Money::from_nano(0);
+        let linear = StorageRewards::new(base_cost);
+        let decay = DecayRewards::new(base_cost, 2.0)?;
+        let senior = get_random_pk();
+        let mut accounts_work: HashMap<_, _> = (1..5).map(|work| (get_random_pk(), work)).collect();
+        let _ = accounts_work.insert(senior, 10);
+        let total_reward = Money::from_nano(1_000_003);
+
+        let linear_dist = linear.distribute(total_reward, accounts_work.clone())?;
+        let decay_dist = decay.distribute(total_reward, accounts_work)?;
+
+        assert!(decay_dist[&senior] > linear_dist[&senior]);
+        // 10^2 / (1 + 4 + 9 + 16 + 100) of the total, rounded down.
+        assert_eq!(decay_dist[&senior], Money::from_nano(769_233));
+        assert_eq!(
+            decay_dist.values().map(|s| s.as_nano()).sum::<u64>(),
+            total_reward.as_nano()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decay_rewards_reconcile_to_the_exact_total() -> Result<()> {
+        let decay = DecayRewards::new(Money::from_nano(0), 3.5)?;
+        let accounts_work: HashMap<_, _> = (1..8)
+            .map(|i| (get_random_pk(), i * 1_000_000_007))
+            .chain(vec![(get_random_pk(), 0)])
+            .collect();
+        for total_reward in &[0, 1, 7, 12_345, u64::MAX] {
+            let dist = decay.distribute(Money::from_nano(*total_reward), accounts_work.clone())?;
+            assert_eq!(dist.len(), accounts_work.len());
+            let sum: u128 = dist.values().map(|s| s.as_nano() as u128).sum();
+            assert_eq!(sum, *total_reward as u128);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decay_rewards_reject_invalid_alpha() {
+        for alpha in &[f64::NAN, f64::INFINITY, 0.5, 0.0, -2.0] {
+            assert!(DecayRewards::new(Money::zero(), *alpha).is_err());
+        }
+    }
+
+    #[test]
+    fn distribute_with_remainder_keeps_the_dust() {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts_work: HashMap<_, _> = (1..8).map(|work| (get_random_pk(), work)).collect();
+        for total_reward in &[0, 1, 5, 27, 28, 1_000_001, u64::MAX] {
+            let total_reward = Money::from_nano(*total_reward);
+            let (shares, remainder) =
+                calc.distribute_with_remainder(total_reward, accounts_work.clone());
+            let distributed: u64 = shares.values().map(|s| s.as_nano()).sum();
+            assert_eq!(shares.len(), accounts_work.len());
+            assert_eq!(distributed + remainder.as_nano(), total_reward.as_nano());
+            assert!(remainder.as_nano() < accounts_work.len() as u64);
+        }
+        // Fewer nanos than accounts: nothing is forced on any single account.
+        let (shares, remainder) =
+            calc.distribute_with_remainder(Money::from_nano(5), accounts_work.clone());
+        assert!(shares.values().all(|share| share.as_nano() <= 1));
+        assert_eq!(remainder, Money::from_nano(3));
+        // Without work, everything remains.
+        let idle: HashMap<_, _> = (0..3).map(|_| (get_random_pk(), 0)).collect();
+        let (shares, remainder) = calc.distribute_with_remainder(Money::from_nano(9), idle);
+        assert!(shares.values().all(|share| *share == Money::zero()));
+        assert_eq!(remainder, Money::from_nano(9));
+    }
+
+    #[test]
+    fn probabilistic_distribution_agrees_on_the_same_seed() {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let idle = get_random_pk();
+        let mut accounts_work: HashMap<_, _> =
+            (1..10).map(|work| (get_random_pk(), work)).collect();
+        let _ = accounts_work.insert(idle, 0);
+        let total_reward = Money::from_nano(4);
+
+        let winners = |seed| {
+            calc.distribute_probabilistic(total_reward, accounts_work.clone(), seed)
+                .unwrap()
+        };
+        let first = winners([7; 32]);
+
+        assert_eq!(winners([7; 32]), first);
+        assert_eq!(first.values().map(|s| s.as_nano()).sum::<u64>(), 4);
+        assert_eq!(first[&idle], Money::zero());
+        assert!((0..8_u8).any(|i| winners([i; 32]) != first));
+
+        // With enough to go around, only the remainder is drawn.
+        let dist = calc.distribute_probabilistic(Money::from_nano(1_003), accounts_work, [1; 32]);
+        assert_eq!(dist.map(|dist| dist[&idle]), Ok(Money::zero()));
+    }
+
+    #[test]
+    fn probabilistic_distribution_is_verified_from_the_recorded_seed() -> Result<()> {
+        // --- Arrange ---
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let acc = crate::Accumulation::new(Default::default(), Default::default(), None);
+        let accounts_work: HashMap<_, _> = (1..10).map(|work| (get_random_pk(), work)).collect();
+        let seed = [7; 32];
+        let dist =
+            calc.distribute_probabilistic(Money::from_nano(5), accounts_work.clone(), seed)?;
+
+        // --- Act ---
+        let e = acc.accumulate_seeded(vec![1], seed, dist)?;
+
+        // --- Assert ---
+        assert_eq!(e.seed, Some(seed));
+        assert!(calc.verify_probabilistic(&e, &accounts_work));
+        // A nano moved from a winner to another account.
+        let mut forged = e.clone();
+        let winner = *forged
+            .distribution
+            .iter()
+            .find(|(_, share)| share.as_nano() > 0)
+            .map(|(id, _)| id)
+            .unwrap();
+        let other = *accounts_work.keys().find(|id| **id != winner).unwrap();
+        let taken = forged.distribution[&winner].as_nano() - 1;
+        let given = forged.distribution[&other].as_nano() + 1;
+        let _ = forged.distribution.insert(winner, Money::from_nano(taken));
+        let _ = forged.distribution.insert(other, Money::from_nano(given));
+        assert!(!calc.verify_probabilistic(&forged, &accounts_work));
+        // Another seed, or none.
+        assert!(!calc.verify_probabilistic(
+            &RewardsAccumulated {
+                seed: Some([8; 32]),
+                ..e.clone()
+            },
+            &accounts_work
+        ));
+        assert!(!calc.verify_probabilistic(&RewardsAccumulated { seed: None, ..e }, &accounts_work));
+        Ok(())
+    }
+
+    #[test]
+    fn remainder_respects_the_max_share_and_min_reward() -> Result<()> {
+        let mut calc = StorageRewards::new(Money::from_nano(0));
+        calc.set_max_share(Some(Money::from_nano(2)));
+        let (senior, first, second) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let accounts_work: HashMap<_, _> = vec![(senior, 8), (first, 1), (second, 1)]
+            .into_iter()
+            .collect();
+
+        // The senior's 4 nanos are capped at 2, and the excess is redistributed.
+        let (shares, remainder) =
+            calc.distribute_with_remainder(Money::from_nano(6), accounts_work.clone());
+        assert_eq!(shares[&senior], Money::from_nano(2));
+        assert_eq!(shares[&first], Money::from_nano(1));
+        assert_eq!(shares[&second], Money::from_nano(1));
+        assert_eq!(remainder, Money::from_nano(2));
+        // Only those under the cap are drawn, until all are capped.
+        let dist =
+            calc.distribute_probabilistic(Money::from_nano(6), accounts_work.clone(), [3; 32])?;
+        assert!(dist.values().all(|share| *share == Money::from_nano(2)));
+        assert_eq!(
+            calc.distribute_probabilistic(Money::from_nano(7), accounts_work.clone(), [3; 32]),
+            Err(FarmingError::Undistributed(Money::from_nano(1)))
+        );
+
+        // Without a cap, those below the min reward are dropped.
+        calc.set_max_share(None);
+        calc.set_min_reward(Money::from_nano(3));
+        let (shares, remainder) =
+            calc.distribute_with_remainder(Money::from_nano(10), accounts_work.clone());
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[&senior], Money::from_nano(10));
+        assert_eq!(remainder, Money::zero());
+        let dist = calc.distribute_probabilistic(Money::from_nano(10), accounts_work, [3; 32])?;
+        assert_eq!(dist, shares);
+        Ok(())
+    }
+
+    #[test]
+    fn unreconcilable_distribution_is_an_error() {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        // With no accounts, there is nobody to give the reward to.
+        let result = calc.distribute(Money::from_nano(10), HashMap::new());
+        let mismatch = FarmingError::DistributionMismatch {
+            total_reward: Money::from_nano(10),
+            shares_sum: Money::zero(),
+        };
+        assert_eq!(result, Err(mismatch.clone()));
+        // Callers on `safe_nd::Result` get it converted.
+        assert!(matches!(
+            safe_nd::Error::from(mismatch),
+            safe_nd::Error::NetworkOther(_)
+        ));
+    }
+
+    #[test]
+    fn linear_factor_follows_percent_filled() {
+        let factor_fn = LinearFactor { min: 0.5, max: 2.5 };
+
+        assert_eq!(factor_fn.factor(100, 10, 0.0), 0.5);
+        assert_eq!(factor_fn.factor(100, 10, 25.0), 1.0);
+        assert_eq!(factor_fn.factor(7, 1, 50.0), 1.5);
+        assert_eq!(factor_fn.factor(100, 10, 100.0), 2.5);
+        // Out of range input is clamped.
+        assert_eq!(factor_fn.factor(100, 10, 150.0), 2.5);
+        assert_eq!(factor_fn.factor(100, 10, -1.0), 0.5);
+    }
+
+    #[test]
+    fn elders_rewarding_same_data_distribute_identically() -> Result<()> {
+        let calc = StorageRewards::new(Money::from_nano(0));
+        let accounts: Vec<_> = (0..100).map(|_| get_random_pk()).collect();
+        // Separately built maps iterate in different orders, as on different Elders.
+        let elder_view = || -> HashMap<_, _> { accounts.iter().map(|id| (*id, 5)).collect() };
+        let data_hash = [7; 32];
+        let total_reward = Money::from_nano(3);
+
+        let first = calc.distribute_for_data(total_reward, elder_view(), 500, &data_hash)?;
+        let second = calc.distribute_for_data(total_reward, elder_view(), 500, &data_hash)?;
+
+        assert_eq!(first, second);
+        let mut closest = accounts.clone();
+        closest.sort_by_key(|id| xor_distance(id, &data_hash));
+        for (rank, id) in closest.iter().enumerate() {
+            let expected = if rank < 3 { 1 } else { 0 };
+            assert_eq!(first[id], Money::from_nano(expected));
+        }
+        Ok(())
+    }
+
+    /// The distribution computed share by share, for every account,
+    /// as it was before the accounts were grouped by work.
+    fn per_account_distribution(
+        total_reward: u64,
+        accounts_work: &HashMap<AccountId, Work>,
+        all_work: u128,
+        data_hash: Option<&[u8]>,
+    ) -> HashMap<AccountId, Money> {
+        let mut shares: Vec<(AccountId, u64)> = accounts_work
+            .iter()
+            .map(|(id, work)| (*id, proportional_share(total_reward, *work, all_work)))
+            .collect();
+        let shares_sum: u128 = shares.iter().map(|(_, share)| *share as u128).sum();
+        if let Some(data_hash) = data_hash {
+            shares.sort_by_cached_key(|(id, _)| xor_distance(id, data_hash));
+        }
+        match (total_reward as u128).cmp(&shares_sum) {
+            Ordering::Greater => {
+                let remainder = (total_reward as u128 - shares_sum) as u64;
+                if data_hash.is_some() {
+                    let mut candidates: Vec<usize> = (0..shares.len())
+                        .filter(|i| accounts_work[&shares[*i].0] > 0)
+                        .collect();
+                    if candidates.is_empty() {
+                        candidates = (0..shares.len()).collect();
+                    }
+                    let count = candidates.len() as u64;
+                    for (rank, index) in candidates.into_iter().enumerate() {
+                        let extra = if (rank as u64) < remainder % count {
+                            1
+                        } else {
+                            0
+                        };
+                        shares[index].1 += remainder / count + extra;
+                    }
+                } else if !shares.is_empty() {
+                    shares.sort_by_key(|(id, share)| (*share, *id));
+                    let index = shares.len() - 1;
+                    shares[index].1 += remainder;
+                }
+            }
+            Ordering::Less => {
+                let mut diff = shares_sum - total_reward as u128;
+                shares.sort_by_key(|(id, share)| (*share, *id));
+                while diff > 0 {
+                    for (_, share) in shares.iter_mut() {
+                        if 0 == diff {
+                            break;
+                        } else if *share >= 1 {
+                            *share -= 1;
+                            diff -= 1;
+                        }
+                    }
+                }
+            }
+            Ordering::Equal => (),
+        }
+        shares
+            .into_iter()
+            .map(|(id, share)| (id, Money::from_nano(share)))
+            .collect()
+    }
+
+    #[test]
+    fn grouped_distribution_matches_the_per_account_reference() -> Result<()> {
+        // A large section, where most accounts have one of a few work values.
+        let works = [0, 1, 2, 3, 7, 7, 7, 7, 40];
+        let accounts_work: HashMap<_, _> = (0..600)
+            .map(|i| (get_random_pk(), works[i % works.len()]))
+            .collect();
+        let accounts_total = total_work(&accounts_work);
+        let data_hash = [7; 32];
+
+        for total_reward in &[0, 1, 3, 599, 601, 1_000_003, 987_654_321_987] {
+            for all_work in &[accounts_total, accounts_total + 17] {
+                for hash in &[None, Some(&data_hash[..])] {
+                    let grouped = proportional_distribution(
+                        Money::from_nano(*total_reward),
+                        accounts_work.clone(),
+                        *all_work,
+                        *hash,
+                    )?;
+                    let reference =
+                        per_account_distribution(*total_reward, &accounts_work, *all_work, *hash);
+                    assert_eq!(grouped, reference);
+                }
+            }
+        }
+        Ok(())
+    }
 }
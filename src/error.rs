@@ -0,0 +1,160 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use safe_nd::{Error, Money};
+use std::fmt;
+
+/// The result of the farming operations.
+pub type Result<T> = std::result::Result<T, FarmingError>;
+
+/// The farming specific reasons for an operation to fail.
+/// Converts into the `safe_nd::Error` that the operation returned
+/// before these were introduced, so callers can keep using `safe_nd::Result`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FarmingError {
+    /// The account already exists.
+    AccountExists,
+    /// The account does not exist.
+    NoSuchAccount,
+    /// The data has already been rewarded.
+    AlreadyRewarded,
+    /// The distribution has no accounts, so nobody would be rewarded.
+    EmptyDistribution,
+    /// The distribution pays out more than it should, or to accounts it should not.
+    InvalidDistribution,
+    /// The shares of a distribution could not be reconciled with its total reward.
+    DistributionMismatch {
+        /// The total reward to distribute.
+        total_reward: Money,
+        /// The sum of the shares.
+        shares_sum: Money,
+    },
+    /// Fewer counters than the quorum have been reported.
+    QuorumNotMet {
+        /// The number of counters reported.
+        counters: usize,
+        /// The number of counters needed.
+        quorum: usize,
+    },
+    /// The factor is not allowed.
+    FactorOutOfRange(f64),
+    /// The exponent by which work is weighted must be finite and at least 1.
+    AlphaOutOfRange(f64),
+    /// The number of expected counters must be uneven, and at least 3.
+    InvalidExpectedCounters(usize),
+    /// The set already holds the expected number of counters.
+    TooManyCounters,
+    /// The amount exceeds the accumulated reward of the account.
+    ExceedsReward,
+    /// Work can not be transferred to the account it is claimed from.
+    TransferToSelf,
+    /// A sum or total would overflow.
+    Overflow,
+    /// The total work passed in is less than the sum of the work of the accounts.
+    InvalidTotalWork {
+        /// The total work passed in.
+        total_work: u128,
+        /// The sum of the work of the accounts.
+        accounts_work: u128,
+    },
+    /// Every account with work is capped by the max share,
+    /// so the given part of the reward could not be distributed.
+    Undistributed(Money),
+    /// A percentile must be within `[0, 1]`.
+    PercentileOutOfRange(f64),
+    /// Farming is paused, for the given reason.
+    Paused(String),
+    /// A thread panicked while holding the lock of a `SyncAccumulation`,
+    /// which may thus be inconsistent.
+    LockPoisoned,
+}
+
+impl fmt::Display for FarmingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FarmingError::*;
+        match self {
+            AccountExists => write!(f, "Account already exists"),
+            NoSuchAccount => write!(f, "No such account"),
+            AlreadyRewarded => write!(f, "Data has already been rewarded"),
+            EmptyDistribution => write!(f, "Distribution is empty"),
+            InvalidDistribution => write!(f, "Invalid distribution"),
+            DistributionMismatch {
+                total_reward,
+                shares_sum,
+            } => write!(
+                f,
+                "Could not reconcile shares with total reward. total_reward: {}, shares_sum: {}",
+                total_reward.as_nano(),
+                shares_sum.as_nano()
+            ),
+            QuorumNotMet { counters, quorum } => write!(
+                f,
+                "Quorum not met: {} counters, where {} are needed",
+                counters, quorum
+            ),
+            FactorOutOfRange(factor) => write!(f, "Factor out of range: {}", factor),
+            AlphaOutOfRange(alpha) => write!(f, "Alpha out of range: {}", alpha),
+            InvalidExpectedCounters(expected) => write!(
+                f,
+                "Expected counters must be uneven and at least 3, was {}",
+                expected
+            ),
+            TooManyCounters => write!(f, "All expected counters have been added"),
+            ExceedsReward => write!(f, "Amount exceeds the accumulated reward"),
+            TransferToSelf => write!(f, "Can not transfer work to the claiming account"),
+            Overflow => write!(f, "Value would overflow"),
+            InvalidTotalWork {
+                total_work,
+                accounts_work,
+            } => write!(
+                f,
+                "Total work {} is less than the work of the accounts, {}",
+                total_work, accounts_work
+            ),
+            Undistributed(leftover) => write!(
+                f,
+                "All accounts are capped, {} nanos could not be distributed",
+                leftover.as_nano()
+            ),
+            PercentileOutOfRange(percentile) => {
+                write!(f, "Percentile out of range: {}", percentile)
+            }
+            Paused(reason) => write!(f, "Farming is paused: {}", reason),
+            LockPoisoned => write!(f, "Accumulation lock poisoned by a panicked thread"),
+        }
+    }
+}
+
+impl std::error::Error for FarmingError {}
+
+impl From<FarmingError> for Error {
+    fn from(error: FarmingError) -> Self {
+        use FarmingError::*;
+        match error {
+            AccountExists => Error::BalanceExists,
+            NoSuchAccount => Error::NoSuchKey,
+            AlreadyRewarded => Error::DataExists,
+            ExceedsReward | Overflow => Error::ExcessiveValue,
+            DistributionMismatch { .. } | Paused(_) | LockPoisoned => {
+                Error::NetworkOther(error.to_string())
+            }
+            EmptyDistribution
+            | InvalidDistribution
+            | QuorumNotMet { .. }
+            | FactorOutOfRange(_)
+            | AlphaOutOfRange(_)
+            | InvalidTotalWork { .. }
+            | Undistributed(_)
+            | PercentileOutOfRange(_)
+            | InvalidExpectedCounters(_)
+            | TooManyCounters
+            | TransferToSelf => Error::InvalidOperation,
+        }
+    }
+}
@@ -32,24 +32,38 @@
 
 pub use crate::{
     accumulation::Accumulation,
-    calculation::{RewardAlgo, StorageRewards},
+    calculation::{BandwidthRewards, DecayRewards, LinearRewards, RewardAlgo, StorageRewards},
+    error::FarmingError,
+    sync::SyncAccumulation,
+    system::FarmingSystem,
     utils::RewardCounterSet,
 };
 use safe_nd::{AccountId, Money, RewardCounter, Work};
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 ///
 pub mod accumulation;
 ///
 pub mod calculation;
+/// The farming specific errors.
+pub mod error;
+/// Merkle proofs of shares in a distribution,
+/// for light clients to verify their own share.
+pub mod merkle;
+/// A thread-safe wrapper of an accumulation,
+/// for sharing it between the tasks of an Elder.
+pub mod sync;
+/// The end-to-end flow of rewarding,
+/// tying a reward algo and an accumulation together.
+pub mod system;
 /// Used for calculating the median
-/// of a vec of RewardCounters.
+/// of a vec of RewardCounters,
+/// and other helpers.
 pub mod utils;
 
-mod example;
-
 ///
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AccumulationEvent {
     ///
     AccountAdded(AccountAdded),
@@ -57,10 +71,22 @@ pub enum AccumulationEvent {
     RewardsAccumulated(RewardsAccumulated),
     ///
     RewardsClaimed(RewardsClaimed),
+    /// Part of the rewards was claimed, and the account keeps accumulating.
+    RewardsPartiallyClaimed(RewardsClaimed),
+    /// Work was synced with an external source.
+    WorkUpdated(WorkUpdated),
+    /// The account was dropped, together with any reward it had accumulated.
+    AccountRemoved(AccountRemoved),
+    /// The rewards of an account were claimed, and its work moved to another account.
+    RewardsClaimedAndTransferred(RewardsClaimedAndTransferred),
 }
 
+/// The age of a node, as tracked by the network.
+pub type NodeAge = u8;
+
 ///
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AccountAdded {
     /// The account id.
     pub id: AccountId,
@@ -68,15 +94,42 @@ pub struct AccountAdded {
     pub work: Work,
 }
 
+/// An account was removed, e.g. when its node
+/// has left the section, without claiming its reward.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccountRemoved {
+    /// The account id.
+    pub id: AccountId,
+}
+
+/// The work of an account has been updated,
+/// e.g. to be in sync with the network's tracking of node age.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorkUpdated {
+    /// The account id.
+    pub id: AccountId,
+    /// The new total work of the account.
+    pub work: Work,
+}
+
 /// Reward and its distribution has been
 /// calculated, and accumulates with this event.
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RewardsAccumulated {
     /// An identifier of a rewarded "thing", such as a data hash for example.
     /// Makes sure we only accumulate a rewarded action _once_.
     pub id: Vec<u8>,
     ///
     pub distribution: HashMap<AccountId, Money>,
+    /// The size of the rewarded data, if it was recorded.
+    pub num_bytes: Option<u64>,
+    /// The seed of the draws, if the distribution is probabilistic, so that
+    /// they can be replayed, see `StorageRewards::verify_probabilistic`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seed: Option<[u8; 32]>,
 }
 
 /// The accumulation of rewards stops at
@@ -84,6 +137,7 @@ pub struct RewardsAccumulated {
 /// The accumulated work is transfered to another instance,
 /// and the accumulated rewards is paid out.
 #[derive(Clone, Eq, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RewardsClaimed {
     ///
     pub account: AccountId,
@@ -91,6 +145,99 @@ pub struct RewardsClaimed {
     pub rewards: RewardCounter,
 }
 
+/// The rewards of an account are paid out, as with `RewardsClaimed`,
+/// while its accumulated work is transferred to another account,
+/// e.g. when a node migrates its standing to a new instance.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RewardsClaimedAndTransferred {
+    /// The claim of the account transferring its work.
+    pub claim: RewardsClaimed,
+    /// The account receiving the work.
+    pub to: AccountId,
+}
+
+impl fmt::Display for AccumulationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AccumulationEvent::*;
+        match self {
+            AccountAdded(e) => e.fmt(f),
+            RewardsAccumulated(e) => e.fmt(f),
+            RewardsClaimed(e) => e.fmt(f),
+            RewardsPartiallyClaimed(e) => write!(
+                f,
+                "RewardsPartiallyClaimed(account={:?}, reward={} nano)",
+                e.account,
+                e.rewards.reward.as_nano()
+            ),
+            WorkUpdated(e) => e.fmt(f),
+            AccountRemoved(e) => e.fmt(f),
+            RewardsClaimedAndTransferred(e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for AccountAdded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AccountAdded(id={:?}, work={})", self.id, self.work)
+    }
+}
+
+impl fmt::Display for AccountRemoved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AccountRemoved(id={:?})", self.id)
+    }
+}
+
+impl fmt::Display for WorkUpdated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WorkUpdated(id={:?}, work={})", self.id, self.work)
+    }
+}
+
+impl fmt::Display for RewardsAccumulated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Summed as u128, since the shares of an unvalidated event may overflow.
+        let total: u128 = self
+            .distribution
+            .values()
+            .map(|share| share.as_nano() as u128)
+            .sum();
+        write!(
+            f,
+            "RewardsAccumulated(id_len={}, recipients={}, total={} nano)",
+            self.id.len(),
+            self.distribution.len(),
+            total
+        )
+    }
+}
+
+impl fmt::Display for RewardsClaimed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RewardsClaimed(account={:?}, reward={} nano, work={})",
+            self.account,
+            self.rewards.reward.as_nano(),
+            self.rewards.work
+        )
+    }
+}
+
+impl fmt::Display for RewardsClaimedAndTransferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RewardsClaimedAndTransferred(account={:?}, reward={} nano, work={}, to={:?})",
+            self.claim.account,
+            self.claim.rewards.reward.as_nano(),
+            self.claim.rewards.work,
+            self.to
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::{Accumulation, AccumulationEvent};
@@ -108,7 +255,7 @@ mod test {
     #[test]
     fn when_data_was_not_previously_rewarded_reward_accumulates() -> Result<()> {
         // --- Arrange ---
-        let mut acc = Accumulation::new(Default::default(), Default::default());
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
         let account = get_random_pk();
         let data_hash = vec![1, 2, 3];
         let reward = Money::from_nano(10);
@@ -123,7 +270,7 @@ mod test {
         assert!(e.distribution.len() == 1);
         assert!(e.distribution.contains_key(&account));
         assert_eq!(&reward, e.distribution.get(&account).unwrap());
-        acc.apply(AccumulationEvent::RewardsAccumulated(e));
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
 
         // .. and successful.
         match acc.get(&account) {
@@ -133,6 +280,34 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn events_display_as_one_liners() -> Result<()> {
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let (first, second) = (get_random_pk(), get_random_pk());
+
+        let added = acc.add_account_and_apply(first, 7)?;
+        let text = AccumulationEvent::AccountAdded(added).to_string();
+        assert!(text.starts_with("AccountAdded("));
+        assert!(text.contains(&format!("id={:?}", first)));
+        assert!(text.contains("work=7"));
+
+        let distribution = hashmap![first => Money::from_nano(100), second => Money::from_nano(23)];
+        let accumulated = acc.accumulate_and_apply(vec![1, 2, 3], distribution)?;
+        let text = AccumulationEvent::RewardsAccumulated(accumulated).to_string();
+        assert_eq!(
+            text,
+            "RewardsAccumulated(id_len=3, recipients=2, total=123 nano)"
+        );
+
+        let claimed = acc.claim_and_apply(first)?;
+        let text = AccumulationEvent::RewardsClaimed(claimed).to_string();
+        assert!(text.starts_with("RewardsClaimed("));
+        assert!(text.contains(&format!("account={:?}", first)));
+        assert!(text.contains("reward=100 nano"));
+        assert!(!text.contains('\n'));
+        Ok(())
+    }
+
     fn get_random_pk() -> PublicKey {
         PublicKey::from(SecretKey::random().public_key())
     }
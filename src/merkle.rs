@@ -0,0 +1,171 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use safe_nd::{AccountId, Money};
+use std::collections::HashMap;
+use tiny_keccak::{Hasher, Sha3};
+
+/// A SHA3-256 digest.
+pub type Digest = [u8; 32];
+
+/// Proves the share of an account in a distribution,
+/// against the Merkle root of that distribution.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MerkleProof {
+    /// The share of the account.
+    pub share: Money,
+    /// The siblings on the path from the leaf of the account up to the root.
+    pub path: Vec<Sibling>,
+}
+
+/// A sibling on the path of a proof, and which side of the path it is on.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Sibling {
+    /// The sibling is hashed to the left of the path.
+    Left(Digest),
+    /// The sibling is hashed to the right of the path.
+    Right(Digest),
+}
+
+/// Returns the root of a Merkle tree over the distribution,
+/// with one leaf per `(AccountId, Money)`, sorted by account.
+/// Leaves and nodes are hashed with distinct prefixes, and a node
+/// without a sibling is carried up a level as is.
+/// The root of an empty distribution is all zeros.
+pub fn distribution_merkle_root(distribution: &HashMap<AccountId, Money>) -> Digest {
+    let mut level = leaves(distribution);
+    if level.is_empty() {
+        return [0; 32];
+    }
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    level[0]
+}
+
+/// Returns the proof of the share of the account, or None if it is not in the distribution.
+pub fn distribution_proof(
+    distribution: &HashMap<AccountId, Money>,
+    account: &AccountId,
+) -> Option<MerkleProof> {
+    let share = *distribution.get(account)?;
+    let mut accounts: Vec<&AccountId> = distribution.keys().collect();
+    accounts.sort();
+    let mut index = accounts.binary_search(&account).ok()?;
+    let mut level = leaves(distribution);
+    let mut path = vec![];
+    while level.len() > 1 {
+        if index % 2 == 1 {
+            path.push(Sibling::Left(level[index - 1]));
+        } else if let Some(right) = level.get(index + 1) {
+            path.push(Sibling::Right(*right));
+        }
+        level = parent_level(&level);
+        index /= 2;
+    }
+    Some(MerkleProof { share, path })
+}
+
+/// Verifies that the proof of the share of the account leads up to the root.
+pub fn verify_proof(root: &Digest, account: &AccountId, proof: &MerkleProof) -> bool {
+    let mut hash = match leaf_hash(account, proof.share) {
+        Some(hash) => hash,
+        None => return false,
+    };
+    for sibling in &proof.path {
+        hash = match sibling {
+            Sibling::Left(left) => node_hash(left, &hash),
+            Sibling::Right(right) => node_hash(&hash, right),
+        };
+    }
+    &hash == root
+}
+
+fn leaves(distribution: &HashMap<AccountId, Money>) -> Vec<Digest> {
+    let mut shares: Vec<(&AccountId, &Money)> = distribution.iter().collect();
+    shares.sort_by_key(|(id, _)| *id);
+    shares
+        .into_iter()
+        .filter_map(|(id, share)| leaf_hash(id, *share))
+        .collect()
+}
+
+/// Hashes the nodes pairwise, carrying up an unpaired last node as is.
+fn parent_level(level: &[Digest]) -> Vec<Digest> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            _ => pair[0],
+        })
+        .collect()
+}
+
+fn leaf_hash(account: &AccountId, share: Money) -> Option<Digest> {
+    let mut hasher = Sha3::v256();
+    hasher.update(&[0]);
+    hasher.update(&bincode::serialize(account).ok()?);
+    hasher.update(&share.as_nano().to_le_bytes());
+    let mut digest = [0; 32];
+    hasher.finalize(&mut digest);
+    Some(digest)
+}
+
+fn node_hash(left: &Digest, right: &Digest) -> Digest {
+    let mut hasher = Sha3::v256();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    let mut digest = [0; 32];
+    hasher.finalize(&mut digest);
+    digest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use safe_nd::PublicKey;
+    use threshold_crypto::SecretKey;
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+
+    fn distribution(count: u64) -> HashMap<AccountId, Money> {
+        (1..=count)
+            .map(|i| (get_random_pk(), Money::from_nano(i * 10)))
+            .collect()
+    }
+
+    #[test]
+    fn proofs_of_all_shares_verify_against_root() {
+        for count in 1..8 {
+            let dist = distribution(count);
+            let root = distribution_merkle_root(&dist);
+            for account in dist.keys() {
+                let proof = distribution_proof(&dist, account).unwrap();
+                assert_eq!(proof.share, dist[account]);
+                assert!(verify_proof(&root, account, &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_proofs_do_not_verify() {
+        let dist = distribution(5);
+        let root = distribution_merkle_root(&dist);
+        let account = dist.keys().next().unwrap();
+        let mut proof = distribution_proof(&dist, account).unwrap();
+
+        assert!(!verify_proof(&root, &get_random_pk(), &proof));
+        proof.share = Money::from_nano(proof.share.as_nano() + 1);
+        assert!(!verify_proof(&root, account, &proof));
+        assert_eq!(distribution_proof(&dist, &get_random_pk()), None);
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// http://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{
+    accumulation::{Accumulation, Id, SoftCapHook},
+    error::{FarmingError, Result},
+    AccumulationEvent, RewardsAccumulated, RewardsClaimed,
+};
+use safe_nd::{AccountId, Money, RewardCounter};
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// An `Accumulation` that can be shared between threads, such as the tasks of an Elder.
+/// Queries and validations take the read lock, and mutations the write lock.
+/// The soft cap hook is called after the write lock is released, so it may
+/// use the instance as well.
+///
+/// A validation and the application of its event are two separate steps,
+/// between which other threads may apply their events. An event validated with
+/// `accumulate` may thus be stale by the time it is passed to `apply`, so callers
+/// must sequence the two themselves, or use `accumulate_and_apply`, which does both
+/// under the write lock.
+///
+/// Should a thread panic while holding the lock, the state may be inconsistent,
+/// so all further operations return `FarmingError::LockPoisoned`.
+#[derive(Debug)]
+pub struct SyncAccumulation {
+    inner: RwLock<Accumulation>,
+}
+
+impl SyncAccumulation {
+    /// Wraps the accumulation.
+    pub fn new(accumulation: Accumulation) -> Self {
+        Self {
+            inner: RwLock::new(accumulation),
+        }
+    }
+
+    /// Returns the wrapped accumulation.
+    pub fn into_inner(self) -> Result<Accumulation> {
+        self.inner
+            .into_inner()
+            .map_err(|_| FarmingError::LockPoisoned)
+    }
+
+    /// Returns a clone of the counter of the account, if any.
+    pub fn get(&self, account: &AccountId) -> Result<Option<RewardCounter>> {
+        Ok(self.read_lock()?.get(account).cloned())
+    }
+
+    /// Runs the query against the accumulation, under the read lock.
+    pub fn read<R>(&self, query: impl FnOnce(&Accumulation) -> R) -> Result<R> {
+        Ok(query(&*self.read_lock()?))
+    }
+
+    /// See `Accumulation::accumulate`.
+    pub fn accumulate(
+        &self,
+        id: Id,
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<RewardsAccumulated> {
+        self.read_lock()?.accumulate(id, distribution)
+    }
+
+    /// See `Accumulation::claim`.
+    pub fn claim(&self, account: AccountId) -> Result<RewardsClaimed> {
+        self.read_lock()?.claim(account)
+    }
+
+    /// See `Accumulation::apply`.
+    pub fn apply(&self, event: AccumulationEvent) -> Result<()> {
+        let (crossed, hook) = {
+            let mut acc = self.write_lock()?;
+            (acc.apply_deferring_hooks(event)?, acc.soft_cap_hook())
+        };
+        report_soft_cap_crossings(hook, &crossed);
+        Ok(())
+    }
+
+    /// Validates the accumulation and applies its event, under the write lock,
+    /// so that no other event can be applied in between.
+    /// Returns the event, e.g. for logging.
+    pub fn accumulate_and_apply(
+        &self,
+        id: Id,
+        distribution: HashMap<AccountId, Money>,
+    ) -> Result<RewardsAccumulated> {
+        let (e, crossed, hook) = {
+            let mut acc = self.write_lock()?;
+            let e = acc.accumulate(id, distribution)?;
+            let event = AccumulationEvent::RewardsAccumulated(e.clone());
+            (e, acc.apply_deferring_hooks(event)?, acc.soft_cap_hook())
+        };
+        report_soft_cap_crossings(hook, &crossed);
+        Ok(e)
+    }
+
+    fn read_lock(&self) -> Result<RwLockReadGuard<'_, Accumulation>> {
+        self.inner.read().map_err(|_| FarmingError::LockPoisoned)
+    }
+
+    fn write_lock(&self) -> Result<RwLockWriteGuard<'_, Accumulation>> {
+        self.inner.write().map_err(|_| FarmingError::LockPoisoned)
+    }
+}
+
+fn report_soft_cap_crossings(hook: Option<SoftCapHook>, crossed: &[(AccountId, Money)]) {
+    if let Some(hook) = hook {
+        for (id, reward) in crossed {
+            hook(id, *reward);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use safe_nd::PublicKey;
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+    use threshold_crypto::SecretKey;
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+
+    #[test]
+    fn concurrent_rewards_are_accumulated_once() -> Result<()> {
+        // --- Arrange ---
+        let acc = Arc::new(SyncAccumulation::new(Accumulation::new(
+            Default::default(),
+            Default::default(),
+            None,
+        )));
+        let accounts: Vec<_> = (0..4).map(|_| get_random_pk()).collect();
+
+        // --- Act ---
+        // Every thread tries to reward the same 50 pieces of data.
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let acc = Arc::clone(&acc);
+                let accounts = accounts.clone();
+                thread::spawn(move || {
+                    let mut rewarded = 0;
+                    for i in 0..50_u8 {
+                        let distribution = accounts
+                            .iter()
+                            .map(|id| (*id, Money::from_nano(3)))
+                            .collect();
+                        match acc.accumulate_and_apply(vec![i], distribution) {
+                            Ok(_) => rewarded += 1,
+                            Err(error) => assert_eq!(error, FarmingError::AlreadyRewarded),
+                        }
+                    }
+                    rewarded
+                })
+            })
+            .collect();
+        let rewarded: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+        // --- Assert ---
+        assert_eq!(rewarded, 50);
+        assert_eq!(
+            acc.read(|acc| acc.total_accumulated())?,
+            Money::from_nano(600)
+        );
+        for id in &accounts {
+            assert_eq!(acc.get(id)?.map(|c| c.reward), Some(Money::from_nano(150)));
+        }
+        let claim = acc.claim(accounts[0])?;
+        acc.apply(AccumulationEvent::RewardsClaimed(claim))?;
+        assert!(acc.get(&accounts[0])?.is_none());
+        let acc = Arc::try_unwrap(acc).unwrap().into_inner()?;
+        assert_eq!(acc.claimed_total(), Money::from_nano(150));
+        Ok(())
+    }
+
+    #[test]
+    fn soft_cap_hook_is_called_outside_the_lock() -> Result<()> {
+        // --- Arrange ---
+        let mut inner = Accumulation::new(Default::default(), Default::default(), None);
+        let shared: Arc<Mutex<Option<Arc<SyncAccumulation>>>> = Default::default();
+        let unlocked = Arc::new(Mutex::new(vec![]));
+        let (slot, recorded) = (Arc::clone(&shared), Arc::clone(&unlocked));
+        inner.set_soft_cap(Some(Money::from_nano(5)));
+        inner.on_soft_cap_crossed(Arc::new(move |_, _| {
+            if let Some(acc) = &*slot.lock().unwrap() {
+                recorded.lock().unwrap().push(acc.inner.try_write().is_ok());
+            }
+        }));
+        let acc = Arc::new(SyncAccumulation::new(inner));
+        *shared.lock().unwrap() = Some(Arc::clone(&acc));
+
+        let crossing = || {
+            vec![(get_random_pk(), Money::from_nano(5))]
+                .into_iter()
+                .collect()
+        };
+
+        // --- Act ---
+        // Both paths cross the soft cap, and so call the hook.
+        let _ = acc.accumulate_and_apply(vec![1], crossing())?;
+        let e = acc.accumulate(vec![2], crossing())?;
+        acc.apply(AccumulationEvent::RewardsAccumulated(e))?;
+
+        // --- Assert ---
+        let _ = shared.lock().unwrap().take();
+        assert_eq!(*unlocked.lock().unwrap(), vec![true, true]);
+        Ok(())
+    }
+
+    #[test]
+    fn poisoned_lock_is_an_error() {
+        // --- Arrange ---
+        let acc = Arc::new(SyncAccumulation::new(Accumulation::new(
+            Default::default(),
+            Default::default(),
+            None,
+        )));
+        let account = get_random_pk();
+
+        // --- Act ---
+        let poisoner = Arc::clone(&acc);
+        let panicked = thread::spawn(move || {
+            let _guard = poisoner.inner.write().unwrap();
+            panic!("panicking while holding the lock");
+        })
+        .join();
+
+        // --- Assert ---
+        assert!(panicked.is_err());
+        assert_eq!(acc.get(&account), Err(FarmingError::LockPoisoned));
+        assert_eq!(acc.claim(account), Err(FarmingError::LockPoisoned));
+        let distribution = vec![(account, Money::from_nano(1))].into_iter().collect();
+        assert_eq!(
+            acc.accumulate_and_apply(vec![1], distribution),
+            Err(FarmingError::LockPoisoned)
+        );
+    }
+}
@@ -7,32 +7,97 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use super::{calculation::*, AccountId, Accumulation, AccumulationEvent};
-use safe_nd::{Result, RewardCounter, Work};
+use super::{
+    calculation::*,
+    error::{FarmingError, Result},
+    AccountAdded, AccountId, Accumulation, AccumulationEvent, RewardsClaimed,
+};
+use safe_nd::Work;
 use std::collections::HashMap;
 
-struct FarmingSystem<A: RewardAlgo> {
+/// Ties a `RewardAlgo` and an `Accumulation` together,
+/// for the end-to-end flow of adding accounts, rewarding data and claiming rewards.
+/// The events applied are returned, so that callers can persist or forward them.
+///
+/// ```
+/// use safe_farming::{Accumulation, FarmingSystem, StorageRewards};
+/// use safe_nd::{Money, PublicKey};
+/// use threshold_crypto::SecretKey;
+///
+/// # fn main() -> safe_nd::Result<()> {
+/// let algo = StorageRewards::new(Money::from_nano(2));
+/// let acc = Accumulation::new(Default::default(), Default::default(), None);
+/// let mut system = FarmingSystem::new(algo, acc);
+///
+/// let account = PublicKey::from(SecretKey::random().public_key());
+/// let _ = system.add_account(account, 1)?;
+/// // Work cost is 3 bytes + base cost of 2, and the factor doubles it.
+/// let total_reward = system.reward(vec![1, 2, 3], 3, 2.0)?;
+/// assert_eq!(total_reward, Money::from_nano(10));
+///
+/// let claimed = system.claim(account)?;
+/// assert_eq!(claimed.rewards.reward, total_reward);
+/// assert_eq!(claimed.rewards.work, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct FarmingSystem<A: RewardAlgo> {
     farming_algo: A,
     accumulation: Accumulation,
+    paused: Option<String>,
 }
 
-#[allow(unused)]
 impl<A: RewardAlgo> FarmingSystem<A> {
-    ///
+    /// Creates a system rewarding by the algo, accumulating in the accumulation.
     pub fn new(farming_algo: A, accumulation: Accumulation) -> Self {
         Self {
             farming_algo,
             accumulation,
+            paused: None,
+        }
+    }
+
+    /// Pauses all reward processing, e.g. for a maintenance window.
+    /// While paused, `add_account`, `reward` and `claim` are
+    /// rejected with an error carrying the reason.
+    pub fn pause(&mut self, reason: String) {
+        self.paused = Some(reason);
+    }
+
+    /// Resumes reward processing after a pause.
+    pub fn resume(&mut self) {
+        self.paused = None;
+    }
+
+    /// Returns the reason for the pause, if paused.
+    pub fn paused(&self) -> Option<&String> {
+        self.paused.as_ref()
+    }
+
+    fn accounts_work(&self) -> HashMap<AccountId, Work> {
+        self.accumulation
+            .get_all()
+            .iter()
+            .map(|(id, acc)| (*id, acc.work))
+            .collect()
+    }
+
+    fn ensure_not_paused(&self) -> Result<()> {
+        match &self.paused {
+            None => Ok(()),
+            Some(reason) => Err(FarmingError::Paused(reason.clone())),
         }
     }
 
     /// Work is the total work associated with this account id.
     /// It is a strictly incrementing value during the lifetime of
     /// the owner on the network.
-    pub fn add_account(&mut self, id: AccountId, work: Work) -> Result<()> {
+    pub fn add_account(&mut self, id: AccountId, work: Work) -> Result<AccountAdded> {
+        self.ensure_not_paused()?;
         let e = self.accumulation.add_account(id, work)?;
-        self.accumulation.apply(AccumulationEvent::AccountAdded(e));
-        Ok(())
+        self.accumulation
+            .apply(AccumulationEvent::AccountAdded(e.clone()))?;
+        Ok(e)
     }
 
     /// Factor is a number > 0, by which reward will be increased or decreased.
@@ -66,47 +131,178 @@ impl<A: RewardAlgo> FarmingSystem<A> {
         num_bytes: u64,
         factor: f64,
     ) -> Result<safe_nd::Money> {
+        let (total_reward, _) = self.reward_collecting_events(data_hash, num_bytes, factor)?;
+        Ok(total_reward)
+    }
+
+    /// Same as `reward`, but with the factor derived by the factor function
+    /// from the current node count, section count and percent (0 to 100) filled.
+    pub fn reward_with_factor_fn(
+        &mut self,
+        data_hash: Vec<u8>,
+        num_bytes: u64,
+        factor_fn: &impl FactorFn,
+        node_count: u64,
+        section_count: u64,
+        percent_filled: f64,
+    ) -> Result<safe_nd::Money> {
+        let factor = factor_fn.factor(node_count, section_count, percent_filled);
+        self.reward(data_hash, num_bytes, factor)
+    }
+
+    /// Same as `reward`, but also returns the events applied,
+    /// so that they can be forwarded to peers.
+    pub fn reward_collecting_events(
+        &mut self,
+        data_hash: Vec<u8>,
+        num_bytes: u64,
+        factor: f64,
+    ) -> Result<(safe_nd::Money, Vec<AccumulationEvent>)> {
+        self.ensure_not_paused()?;
         // first query for accumulated work of all
-        let accounts_work: HashMap<AccountId, Work> = self
-            .accumulation
-            .get_all()
-            .iter()
-            .map(|(id, acc)| (*id, acc.work))
-            .collect();
+        let accounts_work = self.accounts_work();
         // calculate the work cost for the number of bytes to store
         let work_cost = self.farming_algo.work_cost(num_bytes);
         // scale the reward by the factor
-        let total_reward = self.farming_algo.total_reward(factor, work_cost);
+        let total_reward = self.farming_algo.total_reward(factor, work_cost)?;
         // distribute according to previously performed work
-        let distribution = self.farming_algo.distribute(total_reward, accounts_work);
+        let all_work = self.accumulation.cached_total_work();
+        let distribution = self.farming_algo.distribute_for_data(
+            total_reward,
+            accounts_work,
+            all_work,
+            &data_hash,
+        )?;
 
         // validate the operation
-        let e = self.accumulation.accumulate(data_hash, distribution)?;
+        let e = self
+            .accumulation
+            .accumulate_sized(data_hash, num_bytes, distribution)?;
 
         // apply the result, reward counter is now incremented
         // i.e. both the reward amount and the work performed.
+        let event = AccumulationEvent::RewardsAccumulated(e);
+        self.accumulation.apply(event.clone())?;
+
+        Ok((total_reward, vec![event]))
+    }
+
+    /// Accumulates a distribution computed elsewhere, e.g. by an external
+    /// fairness engine, bypassing `work_cost`, `total_reward` and `distribute`.
+    /// The distribution must not pay accounts without work, nor overflow.
+    /// Returns the total of the distribution.
+    pub fn reward_external(
+        &mut self,
+        data_hash: Vec<u8>,
+        distribution: HashMap<AccountId, safe_nd::Money>,
+    ) -> Result<safe_nd::Money> {
+        self.ensure_not_paused()?;
+        let accounts_work = self.accounts_work();
+        validate_no_zero_work_reward(&distribution, &accounts_work)?;
+        let mut total_reward = safe_nd::Money::zero();
+        for share in distribution.values() {
+            total_reward = total_reward
+                .checked_add(*share)
+                .ok_or(FarmingError::Overflow)?;
+        }
+
+        let e = self.accumulation.accumulate(data_hash, distribution)?;
         self.accumulation
-            .apply(AccumulationEvent::RewardsAccumulated(e));
+            .apply(AccumulationEvent::RewardsAccumulated(e))?;
 
         Ok(total_reward)
     }
 
-    pub fn claim(&mut self, id: AccountId) -> Result<RewardCounter> {
+    /// Returns the share of `total_reward` of every existing account, as currently
+    /// distributed, and as it would be distributed after the `newcomer` account with
+    /// `new_account_work` joined, i.e. `(current_share, share_after_join)`.
+    /// An account left out of the distribution after the join, e.g. as dust
+    /// below the min reward, has a share of zero.
+    /// Since ties are broken by account id, the preview depends on the newcomer id.
+    /// Returns `FarmingError::AccountExists` if the newcomer is an existing account,
+    /// or an error if either distribution fails.
+    pub fn dilution_preview(
+        &self,
+        newcomer: AccountId,
+        new_account_work: Work,
+        total_reward: safe_nd::Money,
+    ) -> Result<HashMap<AccountId, (safe_nd::Money, safe_nd::Money)>> {
+        let accounts_work = self.accounts_work();
+        if accounts_work.contains_key(&newcomer) {
+            return Err(FarmingError::AccountExists);
+        }
+        let current = self
+            .farming_algo
+            .distribute(total_reward, accounts_work.clone())?;
+        let mut joined = accounts_work;
+        let _ = joined.insert(newcomer, new_account_work);
+        let after_join = self.farming_algo.distribute(total_reward, joined)?;
+        Ok(current
+            .into_iter()
+            .map(|(id, share)| {
+                let diluted = after_join
+                    .get(&id)
+                    .copied()
+                    .unwrap_or_else(safe_nd::Money::zero);
+                (id, (share, diluted))
+            })
+            .collect())
+    }
+
+    /// Claims all the rewards of the account, which is removed.
+    pub fn claim(&mut self, id: AccountId) -> Result<RewardsClaimed> {
+        self.ensure_not_paused()?;
         let e = self.accumulation.claim(id)?;
         self.accumulation
-            .apply(AccumulationEvent::RewardsClaimed(e.clone()));
-        Ok(e.rewards)
+            .apply(AccumulationEvent::RewardsClaimed(e.clone()))?;
+        Ok(e)
+    }
+}
+
+/// The whole state of a `FarmingSystem`, i.e. the algo config
+/// together with the accumulation, for persisting it in one go.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FarmingSnapshot<A> {
+    /// The reward algo, with its config.
+    pub farming_algo: A,
+    /// The accumulation state.
+    pub accumulation: Accumulation,
+    /// The reason for the pause, if paused.
+    pub paused: Option<String>,
+}
+
+impl<A: RewardAlgo + Clone> FarmingSystem<A> {
+    /// Returns a snapshot of the whole system.
+    pub fn snapshot(&self) -> FarmingSnapshot<A> {
+        FarmingSnapshot {
+            farming_algo: self.farming_algo.clone(),
+            accumulation: self.accumulation.clone(),
+            paused: self.paused.clone(),
+        }
+    }
+
+    /// Restores a system from a snapshot.
+    pub fn restore(snapshot: FarmingSnapshot<A>) -> Self {
+        Self {
+            farming_algo: snapshot.farming_algo,
+            accumulation: snapshot.accumulation,
+            paused: snapshot.paused,
+        }
     }
 }
 
 #[allow(unused)]
 mod test {
-    use super::{Accumulation, FarmingSystem, RewardCounter, StorageRewards};
-    use crate::RewardCounterSet;
+    use super::{Accumulation, FarmingSystem, LinearFactor, StorageRewards};
+    use crate::{
+        error::{FarmingError, Result},
+        RewardCounterSet,
+    };
     use crdts::quickcheck::{quickcheck, Arbitrary, TestResult};
     use rand::{Rng, RngCore};
     use rayon::prelude::*;
-    use safe_nd::{Money, PublicKey, Result};
+    use safe_nd::{Money, PublicKey, RewardCounter};
     use std::collections::{HashMap, HashSet};
     use threshold_crypto::SecretKey;
 
@@ -138,7 +334,7 @@ mod test {
     #[test]
     fn farming_system() -> Result<()> {
         // --- Arrange ---
-        let acc = Accumulation::new(Default::default(), Default::default());
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
         let base_cost = Money::from_nano(2);
         let algo = StorageRewards::new(base_cost);
         let mut system = FarmingSystem::new(algo, acc);
@@ -152,13 +348,14 @@ mod test {
 
         // --- Act ---
         // Try accumulate.
-        system.add_account(account, work)?;
+        let _ = system.add_account(account, work)?;
         let _ = system.reward(data_hash, num_bytes, factor as f64)?;
 
         // --- Assert ---
         match system.claim(account) {
             Err(err) => panic!(err),
             Ok(e) => {
+                let e = e.rewards;
                 assert!(e.reward.as_nano() == factor * (num_bytes + base_cost.as_nano()));
                 assert!(e.work == work + 1); // being part of 1 reward occasion
             }
@@ -166,6 +363,216 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn replaying_collected_events_reproduces_state() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let mut replica = get_instance(2);
+        for work in 1..4 {
+            let account = get_random_pk();
+            let _ = system.add_account(account, work)?;
+            let _ = replica.add_account(account, work)?;
+        }
+
+        // --- Act ---
+        let mut events = vec![];
+        for i in 0..3 {
+            let (_, applied) = system.reward_collecting_events(vec![i], 1000, 1.5)?;
+            events.extend(applied);
+        }
+        for event in events {
+            replica.accumulation.apply(event)?;
+        }
+
+        // --- Assert ---
+        assert_eq!(
+            system.accumulation.get_all(),
+            replica.accumulation.get_all()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn when_paused_operations_are_rejected_with_reason() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let account = get_random_pk();
+        let _ = system.add_account(account, 1)?;
+        let reason = "upgrading".to_string();
+        let expected = Err(FarmingError::Paused(reason.clone()));
+
+        // --- Act ---
+        system.pause(reason.clone());
+
+        // --- Assert ---
+        assert_eq!(system.paused(), Some(&reason));
+        assert_eq!(system.add_account(get_random_pk(), 1).map(|_| ()), expected);
+        assert_eq!(
+            system.reward(vec![1, 2, 3], 3, 1.0),
+            expected.clone().map(|_| Money::zero())
+        );
+        assert_eq!(
+            system.claim(account).map(|e| e.rewards),
+            expected.map(|_| Default::default())
+        );
+
+        // .. and after resuming, operations succeed again.
+        system.resume();
+        assert_eq!(system.paused(), None);
+        let _ = system.reward(vec![1, 2, 3], 3, 1.0)?;
+        let _ = system.claim(account)?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_factors_are_rejected() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let account = get_random_pk();
+        let _ = system.add_account(account, 1)?;
+
+        // --- Act + Assert ---
+        for factor in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -1.0, 0.0] {
+            let result = system.reward(vec![1, 2, 3], 3, *factor);
+            assert!(matches!(result, Err(FarmingError::FactorOutOfRange(_))));
+        }
+        // Nothing was accumulated.
+        assert_eq!(system.claim(account)?.rewards.reward, Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn external_distribution_is_accumulated() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let (first, second, idle) = (get_random_pk(), get_random_pk(), get_random_pk());
+        let _ = system.add_account(first, 1)?;
+        let _ = system.add_account(second, 4)?;
+        let _ = system.add_account(idle, 0)?;
+        let share = |id, amount| (id, Money::from_nano(amount));
+        let distribution = vec![share(first, 70), share(second, 30)]
+            .into_iter()
+            .collect();
+
+        // --- Act ---
+        let total = system.reward_external(vec![1, 2, 3], distribution)?;
+
+        // --- Assert ---
+        assert_eq!(total, Money::from_nano(100));
+        let counter = |reward, work| RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
+        };
+        assert_eq!(system.accumulation.get(&first), Some(&counter(70, 2)));
+        assert_eq!(system.accumulation.get(&second), Some(&counter(30, 5)));
+        let pays_idle = vec![share(idle, 1)].into_iter().collect();
+        assert_eq!(
+            system.reward_external(vec![4], pays_idle),
+            Err(FarmingError::InvalidDistribution)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shares_dilute_when_a_high_work_member_joins() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let (senior, junior) = (get_random_pk(), get_random_pk());
+        let _ = system.add_account(senior, 30)?;
+        let _ = system.add_account(junior, 10)?;
+
+        let newcomer = get_random_pk();
+
+        // --- Act ---
+        let preview = system.dilution_preview(newcomer, 60, Money::from_nano(1_000))?;
+
+        // --- Assert ---
+        assert_eq!(preview.len(), 2);
+        assert_eq!(
+            preview[&senior],
+            (Money::from_nano(750), Money::from_nano(300))
+        );
+        assert_eq!(
+            preview[&junior],
+            (Money::from_nano(250), Money::from_nano(100))
+        );
+        assert_eq!(
+            system.dilution_preview(junior, 60, Money::from_nano(1_000)),
+            Err(FarmingError::AccountExists)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accounts_dropped_as_dust_after_a_join_are_previewed_at_zero() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        system.farming_algo.set_min_reward(Money::from_nano(150));
+        let (senior, junior) = (get_random_pk(), get_random_pk());
+        let _ = system.add_account(senior, 30)?;
+        let _ = system.add_account(junior, 10)?;
+
+        // --- Act ---
+        let preview = system.dilution_preview(get_random_pk(), 60, Money::from_nano(1_000))?;
+
+        // --- Assert ---
+        // The junior would get 100 nanos, below the min reward.
+        assert_eq!(preview[&junior], (Money::from_nano(250), Money::zero()));
+        assert!(preview[&senior].1 >= Money::from_nano(300));
+        Ok(())
+    }
+
+    #[test]
+    fn restored_snapshot_rewards_identically() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(7);
+        for work in 1..5 {
+            let _ = system.add_account(get_random_pk(), work)?;
+        }
+        let _ = system.reward(vec![1], 1_000, 1.5)?;
+
+        // --- Act ---
+        let mut restored = FarmingSystem::restore(system.snapshot());
+
+        // --- Assert ---
+        assert_eq!(
+            restored.accumulation.get_all(),
+            system.accumulation.get_all()
+        );
+        assert_eq!(
+            restored.reward(vec![2], 3_000, 0.8),
+            system.reward(vec![2], 3_000, 0.8)
+        );
+        assert_eq!(
+            restored.accumulation.get_all(),
+            system.accumulation.get_all()
+        );
+        // The restored idempotency rejects what was already rewarded.
+        assert_eq!(
+            restored.reward(vec![1], 1_000, 1.5),
+            Err(FarmingError::AlreadyRewarded)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reward_is_scaled_by_factor_fn() -> Result<()> {
+        // --- Arrange ---
+        let mut system = get_instance(2);
+        let account = get_random_pk();
+        let _ = system.add_account(account, 1)?;
+        let factor_fn = LinearFactor { min: 1.0, max: 3.0 };
+
+        // --- Act ---
+        // Half filled, so the factor is 2.
+        let total_reward = system.reward_with_factor_fn(vec![1], 8, &factor_fn, 100, 10, 50.0)?;
+
+        // --- Assert ---
+        assert_eq!(total_reward, Money::from_nano(20));
+        assert_eq!(system.claim(account)?.rewards.reward, total_reward);
+        Ok(())
+    }
+
     // #[test]
     // fn quickcheck_bft_rewards() {
     //     quickcheck(bft_rewards_quickcheck as fn(Factor) -> TestResult);
@@ -352,7 +759,7 @@ mod test {
             let account = get_random_pk();
             accounts.push(account);
             for elder in &mut elders {
-                elder.add_account(account, *work).unwrap();
+                let _ = elder.add_account(account, *work).unwrap();
             }
         }
 
@@ -396,7 +803,7 @@ mod test {
         for account in accounts {
             let counters: Vec<RewardCounter> = (&mut elders)
                 .par_iter_mut()
-                .map(|elder| elder.claim(account).unwrap())
+                .map(|elder| elder.claim(account).unwrap().rewards)
                 .collect();
             let counters =
                 RewardCounterSet::new(num_elders as usize, apply_byzantine_faults(counters))?;
@@ -449,7 +856,7 @@ mod test {
     }
 
     fn get_instance(base_cost: u64) -> Elder {
-        let acc = Accumulation::new(Default::default(), Default::default());
+        let acc = Accumulation::new(Default::default(), Default::default(), None);
         let base_cost = Money::from_nano(base_cost);
         let algo = StorageRewards::new(base_cost);
         FarmingSystem::new(algo, acc)
@@ -7,7 +7,13 @@
 // specific language governing permissions and limitations relating to use of the SAFE Network
 // Software.
 
-use safe_nd::{Error, Money, Result, RewardCounter};
+use super::{
+    error::{FarmingError, Result},
+    AccumulationEvent, NodeAge,
+};
+use safe_nd::{AccountId, Money, RewardCounter, Work};
+use std::collections::HashSet;
+use tiny_keccak::{Hasher, Sha3};
 
 /// A util for calculating the median
 /// of a vec of RewardCounters.
@@ -15,11 +21,49 @@ use safe_nd::{Error, Money, Result, RewardCounter};
 /// the semantics of this set is that it
 /// basically represents a single value, which we
 /// derive by taking the median of the set.
+#[derive(Debug)]
 pub struct RewardCounterSet {
+    expected_counters: usize,
     quorum: usize,
     counters: Vec<RewardCounter>,
 }
 
+/// An agreed value, and how it was derived.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AgreedValue {
+    /// The agreed counter.
+    pub counter: RewardCounter,
+    /// Whether the value was interpolated, i.e. the average of the
+    /// two middle values of an even-length set, and thus not reported by anyone.
+    pub is_interpolated: bool,
+}
+
+/// How much the counters of a set disagree.
+/// The variances are of the population, i.e. divided by the number of counters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dispersion {
+    /// The mean reward, in nanos.
+    pub mean_reward: f64,
+    /// The variance of the rewards, in nanos squared.
+    pub reward_variance: f64,
+    /// The mean work.
+    pub mean_work: f64,
+    /// The variance of the work.
+    pub work_variance: f64,
+}
+
+/// How to pick the median of an even number of values,
+/// where there is no single middle value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EvenStrategy {
+    /// The average of the two middle values. This is what `agreed_value` uses.
+    Average,
+    /// The lower of the two middle values, e.g. to bias towards a conservative payout.
+    Lower,
+    /// The upper of the two middle values.
+    Upper,
+}
+
 /// The semantics of RewardCounterSet is that it
 /// basically represents a single value, as perceived
 /// by a fix set of different actors working as a group.
@@ -31,10 +75,14 @@ impl RewardCounterSet {
     /// The vec can be empty or contain any number already.
     pub fn new(expected_counters: usize, counters: Vec<RewardCounter>) -> Result<Self> {
         if expected_counters % 2 == 0 || 3 > expected_counters {
-            return Err(Error::InvalidOperation);
+            return Err(FarmingError::InvalidExpectedCounters(expected_counters));
         }
         let quorum = (expected_counters / 3) * 2;
-        Ok(Self { quorum, counters })
+        Ok(Self {
+            expected_counters,
+            quorum,
+            counters,
+        })
     }
 
     /// Returns the length of the set.
@@ -48,62 +96,666 @@ impl RewardCounterSet {
     }
 
     /// Adds a counter to the set.
-    pub fn add(&mut self, counter: RewardCounter) {
-        self.counters.push(counter)
+    /// Returns `FarmingError::TooManyCounters` if the set already holds
+    /// the expected number of counters, so that extra counters can not skew the median.
+    pub fn add(&mut self, counter: RewardCounter) -> Result<()> {
+        if self.counters.len() >= self.expected_counters {
+            return Err(FarmingError::TooManyCounters);
+        }
+        self.counters.push(counter);
+        Ok(())
     }
 
     /// Returns the agreed value between all,
     /// interpreted through the median value.
     pub fn agreed_value(&self) -> Option<RewardCounter> {
+        self.try_agreed_value().ok()
+    }
+
+    /// Same as `agreed_value`, but tells why there is no agreed value.
+    pub fn try_agreed_value(&self) -> std::result::Result<RewardCounter, FarmingError> {
         let count = self.counters.len();
         if self.quorum > count {
-            return None;
+            return Err(FarmingError::QuorumNotMet {
+                counters: count,
+                quorum: self.quorum,
+            });
         }
 
         let median_reward = self.median_reward();
         let median_work = self.median_work();
 
-        Some(RewardCounter {
+        Ok(RewardCounter {
             reward: median_reward,
             work: median_work,
         })
     }
 
-    fn median_reward(&self) -> Money {
-        let mut rewards: Vec<Money> = self
+    /// Same as `agreed_value`, but with the given strategy for sets of even length,
+    /// applied to both the reward and the work.
+    pub fn agreed_value_even_strategy(&self, strategy: EvenStrategy) -> Option<RewardCounter> {
+        if self.quorum > self.counters.len() {
+            return None;
+        }
+        let rewards = self.counters.iter().map(|c| c.reward.as_nano()).collect();
+        let works = self.counters.iter().map(|c| c.work).collect();
+        Some(RewardCounter {
+            reward: Money::from_nano(median(rewards, strategy)),
+            work: median(works, strategy),
+        })
+    }
+
+    /// Returns the agreed value as the weighted median of the set, for when some
+    /// reporters are more trusted than others: `weights[i]` is the weight of the
+    /// i:th counter, and for both reward and work, the value where the cumulative
+    /// weight (in order of value) reaches half the total weight is picked.
+    /// The quorum is by weight rather than by count: the counters must carry more than
+    /// 2/3 of `total_weight`, which is the weight of all expected reporters, including
+    /// those that have not reported. None if they do not, or if the number of weights
+    /// differs from the number of counters.
+    pub fn agreed_value_weighted(
+        &self,
+        weights: &[u64],
+        total_weight: u64,
+    ) -> Option<RewardCounter> {
+        if weights.len() != self.counters.len() {
+            return None;
+        }
+        let reported: u128 = weights.iter().map(|weight| *weight as u128).sum();
+        if 3 * reported <= 2 * total_weight as u128 {
+            return None;
+        }
+        let rewards = self
             .counters
-            .clone()
-            .into_iter()
-            .map(|c| c.reward)
+            .iter()
+            .map(|c| c.reward.as_nano())
+            .zip(weights.iter().copied())
             .collect();
+        let works = self
+            .counters
+            .iter()
+            .map(|c| c.work)
+            .zip(weights.iter().copied())
+            .collect();
+        Some(RewardCounter {
+            reward: Money::from_nano(weighted_median(rewards)?),
+            work: weighted_median(works)?,
+        })
+    }
 
-        rewards.sort();
+    /// Returns the agreed value as the trimmed mean of the set: rewards and work
+    /// are sorted independently, `trim` values are dropped from each end (e.g. the
+    /// byzantine outliers), and the rest is averaged, rounding down.
+    /// Unlike the median, this uses all remaining values, which converges faster
+    /// to the honest value when honest reporters drift slightly.
+    /// None if fewer counters than the quorum remain after trimming.
+    pub fn agreed_value_trimmed(&self, trim: usize) -> Option<RewardCounter> {
+        let remaining = self.counters.len().checked_sub(2 * trim)?;
+        if self.quorum > remaining || remaining == 0 {
+            return None;
+        }
+        let rewards = self.counters.iter().map(|c| c.reward.as_nano()).collect();
+        let works = self.counters.iter().map(|c| c.work).collect();
+        Some(RewardCounter {
+            reward: Money::from_nano(trimmed_mean(rewards, trim)),
+            work: trimmed_mean(works, trim),
+        })
+    }
 
-        if rewards.len() % 2 == 0 {
-            let mid_0 = rewards.len() / 2;
-            let mid_1 = (rewards.len() / 2) + 1;
-            let mid_0 = rewards.clone().remove(mid_0).as_nano();
-            let mid_1 = rewards.remove(mid_1).as_nano();
-            Money::from_nano((mid_0 + mid_1) / 2)
-        } else {
-            let mid = rewards.len() / 2;
-            rewards.remove(mid)
+    /// Returns the mean and variance of the reported rewards and work,
+    /// e.g. to alarm when the reporters drift apart. None if the set is empty.
+    pub fn dispersion(&self) -> Option<Dispersion> {
+        if self.counters.is_empty() {
+            return None;
+        }
+        let rewards: Vec<u64> = self.counters.iter().map(|c| c.reward.as_nano()).collect();
+        let works: Vec<u64> = self.counters.iter().map(|c| c.work).collect();
+        let (mean_reward, reward_variance) = mean_and_variance(&rewards);
+        let (mean_work, work_variance) = mean_and_variance(&works);
+        Some(Dispersion {
+            mean_reward,
+            reward_variance,
+            mean_work,
+            work_variance,
+        })
+    }
+
+    /// Returns the coefficient of variation (standard deviation / mean) of the
+    /// reported rewards, as a measure of how much the reporters disagree,
+    /// even when the median is usable.
+    /// None if there are fewer counters than the quorum, or the mean is zero.
+    pub fn reward_cv(&self) -> Option<f64> {
+        if self.quorum > self.counters.len() {
+            return None;
+        }
+        let dispersion = self.dispersion()?;
+        if dispersion.mean_reward == 0.0 {
+            return None;
         }
+        Some(dispersion.reward_variance.sqrt() / dispersion.mean_reward)
+    }
+
+    /// Returns a digest binding the account to the agreed value,
+    /// so that the agreement can be referenced without the counters.
+    /// It is the SHA3-256 of the serialised account id, followed by
+    /// the agreed reward (in nanos) and work, as little endian u64s.
+    /// None if there is no agreed value yet.
+    pub fn agreed_digest(&self, account: &AccountId) -> Option<[u8; 32]> {
+        let agreed = self.agreed_value()?;
+        let mut hasher = Sha3::v256();
+        hasher.update(&bincode::serialize(account).ok()?);
+        hasher.update(&agreed.reward.as_nano().to_le_bytes());
+        hasher.update(&agreed.work.to_le_bytes());
+        let mut digest = [0; 32];
+        hasher.finalize(&mut digest);
+        Some(digest)
+    }
+
+    /// Same as `agreed_value`, but also tells whether the value
+    /// was interpolated (for even-length sets) or directly reported.
+    pub fn agreed_value_detailed(&self) -> Option<AgreedValue> {
+        let counter = self.agreed_value()?;
+        Some(AgreedValue {
+            counter,
+            is_interpolated: self.counters.len() % 2 != 1,
+        })
+    }
+
+    fn median_reward(&self) -> Money {
+        let rewards = self.counters.iter().map(|c| c.reward.as_nano()).collect();
+        Money::from_nano(median(rewards, EvenStrategy::Average))
     }
 
     fn median_work(&self) -> u64 {
-        let mut works: Vec<u64> = self.counters.clone().into_iter().map(|c| c.work).collect();
-        works.sort();
-
-        if works.len() % 2 == 0 {
-            let mid_0 = works.len() / 2;
-            let mid_1 = (works.len() / 2) + 1;
-            let mid_0 = works.clone().remove(mid_0);
-            let mid_1 = works.remove(mid_1);
-            (mid_0 + mid_1) / 2
+        let works = self.counters.iter().map(|c| c.work).collect();
+        median(works, EvenStrategy::Average)
+    }
+}
+
+/// The median of the values, picked by the strategy when their number is even.
+/// Zero if there are no values.
+fn median(mut values: Vec<u64>, strategy: EvenStrategy) -> u64 {
+    if values.is_empty() {
+        return 0;
+    }
+    values.sort();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        return values[mid];
+    }
+    let (lower, upper) = (values[mid - 1], values[mid]);
+    match strategy {
+        // Values are sorted, so this can not overflow.
+        EvenStrategy::Average => lower + (upper - lower) / 2,
+        EvenStrategy::Lower => lower,
+        EvenStrategy::Upper => upper,
+    }
+}
+
+/// The mean and population variance of the values, which must not be empty.
+/// The sum is taken as u128, so that the mean does not overflow.
+fn mean_and_variance(values: &[u64]) -> (f64, f64) {
+    let count = values.len() as f64;
+    let sum: u128 = values.iter().map(|value| *value as u128).sum();
+    let mean = sum as f64 / count;
+    let variance = values
+        .iter()
+        .map(|value| (*value as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    (mean, variance)
+}
+
+/// The lowest value where the cumulative weight, in order of value,
+/// reaches half the total weight. None if the total weight is zero.
+fn weighted_median(mut values: Vec<(u64, u64)>) -> Option<u64> {
+    let total: u128 = values.iter().map(|(_, weight)| *weight as u128).sum();
+    values.sort();
+    let mut cumulative = 0;
+    for (value, weight) in values {
+        cumulative += weight as u128;
+        if cumulative > 0 && 2 * cumulative >= total {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The mean of the values, without the `trim` lowest and highest, rounded down.
+/// Summed as u128, so it can not overflow. Expects values to remain after trimming.
+fn trimmed_mean(mut values: Vec<u64>, trim: usize) -> u64 {
+    values.sort();
+    let kept = &values[trim..values.len() - trim];
+    let sum: u128 = kept.iter().map(|value| *value as u128).sum();
+    // The mean is at most the max value, so it fits.
+    (sum / kept.len() as u128) as u64
+}
+
+/// Returns the total reward paid to the given accounts over a log of events,
+/// i.e. their shares in all `RewardsAccumulated` events, regardless of
+/// whether they have claimed since. Returns `FarmingError::Overflow` on overflow.
+pub fn total_paid_to(accounts: &HashSet<AccountId>, events: &[AccumulationEvent]) -> Result<Money> {
+    let mut total = Money::zero();
+    for event in events {
+        if let AccumulationEvent::RewardsAccumulated(e) = event {
+            for (id, share) in &e.distribution {
+                if accounts.contains(id) {
+                    total = total.checked_add(*share).ok_or(FarmingError::Overflow)?;
+                }
+            }
+        }
+    }
+    Ok(total)
+}
+
+/// Returns the work implied by a node age.
+/// A node ages roughly every time its work doubles,
+/// so the work is taken as `2^age`, saturating at the max value.
+pub fn work_from_age(age: NodeAge) -> Work {
+    1_u64.checked_shl(age as u32).unwrap_or(u64::MAX)
+}
+
+/// Returns the drift of the agreed total from the expected total,
+/// in percent of the expected total, e.g. to alert when aggregation
+/// of claimed rewards drifts beyond some threshold.
+/// When the expected total is zero, the drift is 0 if the agreed total
+/// is zero too, and 100 otherwise.
+pub fn aggregate_drift(expected_total: Money, agreed_total: Money) -> f64 {
+    let expected = expected_total.as_nano() as f64;
+    let agreed = agreed_total.as_nano() as f64;
+    if expected_total.as_nano() == 0 {
+        return if agreed_total.as_nano() == 0 {
+            0.0
         } else {
-            let mid = works.len() / 2;
-            works.remove(mid)
+            100.0
+        };
+    }
+    100.0 * (agreed - expected).abs() / expected
+}
+
+/// The number of nanos in one whole SAFE coin.
+pub const NANOS_PER_COIN: u64 = 1_000_000_000;
+
+/// Returns the amount of whole coins as Money,
+/// saturating at the max value.
+pub fn money_from_coins(coins: u64) -> Money {
+    Money::from_nano(coins.saturating_mul(NANOS_PER_COIN))
+}
+
+/// Splits the amount into whole coins and remaining nanos.
+pub fn coins_of(amount: Money) -> (u64, u64) {
+    let nanos = amount.as_nano();
+    (nanos / NANOS_PER_COIN, nanos % NANOS_PER_COIN)
+}
+
+/// Returns the account closest to the data hash, measured as the XOR distance
+/// between the serialised account id and the hash.
+/// Can be used to deterministically pick an account for a piece of data.
+pub fn closest_account<'a>(
+    accounts: impl Iterator<Item = &'a AccountId>,
+    data_hash: &[u8],
+) -> Option<&'a AccountId> {
+    accounts.min_by_key(|id| xor_distance(id, data_hash))
+}
+
+/// XOR of the serialised account id and the hash,
+/// where the shorter of the two is padded with zeros.
+pub(crate) fn xor_distance(id: &AccountId, data_hash: &[u8]) -> Vec<u8> {
+    let id = bincode::serialize(id).unwrap_or_default();
+    let len = id.len().max(data_hash.len());
+    (0..len)
+        .map(|i| id.get(i).unwrap_or(&0) ^ data_hash.get(i).unwrap_or(&0))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Accumulation;
+    use safe_nd::PublicKey;
+    use threshold_crypto::SecretKey;
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+
+    #[test]
+    fn total_paid_to_includes_claimed_rewards() -> Result<()> {
+        let mut acc = Accumulation::new(Default::default(), Default::default(), None);
+        let first = get_random_pk();
+        let second = get_random_pk();
+        let outsider = get_random_pk();
+        let mut events = vec![];
+        let shares = vec![(first, 10), (second, 20), (outsider, 40)];
+        let distribution = shares
+            .into_iter()
+            .map(|(id, share)| (id, Money::from_nano(share)))
+            .collect();
+        events.push(AccumulationEvent::RewardsAccumulated(
+            acc.accumulate(vec![1], distribution)?,
+        ));
+        acc.apply(events[0].clone())?;
+        events.push(AccumulationEvent::RewardsClaimed(acc.claim(first)?));
+        acc.apply(events[1].clone())?;
+        let distribution = vec![(second, Money::from_nano(5))].into_iter().collect();
+        events.push(AccumulationEvent::RewardsAccumulated(
+            acc.accumulate(vec![2], distribution)?,
+        ));
+
+        let accounts = vec![first, second].into_iter().collect();
+        assert_eq!(total_paid_to(&accounts, &events)?, Money::from_nano(35));
+        assert_eq!(total_paid_to(&HashSet::new(), &events)?, Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn work_doubles_with_age() {
+        assert_eq!(work_from_age(0), 1);
+        assert_eq!(work_from_age(5), 32);
+        assert_eq!(work_from_age(63), 1 << 63);
+        assert_eq!(work_from_age(64), u64::MAX);
+        assert_eq!(work_from_age(255), u64::MAX);
+    }
+
+    #[test]
+    fn closest_account_is_found_by_xor_distance() {
+        let accounts: Vec<AccountId> = (0..5).map(|_| get_random_pk()).collect();
+        let target = accounts[3];
+        let mut data_hash = bincode::serialize(&target).unwrap();
+        let last = data_hash.len() - 1;
+        data_hash[last] ^= 1;
+
+        assert_eq!(closest_account(accounts.iter(), &data_hash), Some(&target));
+        assert_eq!(closest_account([].iter(), &data_hash), None);
+    }
+
+    fn counter(reward: u64, work: u64) -> RewardCounter {
+        RewardCounter {
+            reward: Money::from_nano(reward),
+            work,
         }
     }
+
+    #[test]
+    fn agreed_value_of_even_length_set_is_interpolated() -> Result<()> {
+        let counters = vec![
+            counter(10, 2),
+            counter(11, 3),
+            counter(12, 4),
+            counter(13, 5),
+        ];
+        let set = RewardCounterSet::new(5, counters)?;
+
+        let agreed = set.agreed_value_detailed();
+
+        assert_eq!(agreed.map(|a| a.is_interpolated), Some(true));
+        Ok(())
+    }
+
+    #[test]
+    fn agreed_value_of_odd_length_set_is_reported() -> Result<()> {
+        let counters = vec![counter(10, 2), counter(11, 3), counter(12, 4)];
+        let set = RewardCounterSet::new(3, counters)?;
+
+        let agreed = set.agreed_value_detailed();
+
+        assert_eq!(
+            agreed,
+            Some(AgreedValue {
+                counter: counter(11, 3),
+                is_interpolated: false,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identical_agreements_have_identical_digests() -> Result<()> {
+        let account = get_random_pk();
+        let counters = vec![counter(10, 2), counter(10, 2), counter(11, 3)];
+        let set = RewardCounterSet::new(3, counters.clone())?;
+        let same = RewardCounterSet::new(3, counters)?;
+        let changed =
+            RewardCounterSet::new(3, vec![counter(12, 2), counter(12, 2), counter(11, 3)])?;
+        let below_quorum = RewardCounterSet::new(3, vec![counter(10, 2)])?;
+
+        let digest = set.agreed_digest(&account);
+
+        assert!(digest.is_some());
+        assert_eq!(digest, same.agreed_digest(&account));
+        assert_ne!(digest, changed.agreed_digest(&account));
+        assert_ne!(digest, set.agreed_digest(&get_random_pk()));
+        assert_eq!(below_quorum.agreed_digest(&account), None);
+        Ok(())
+    }
+
+    #[test]
+    fn coins_round_trip_through_money() {
+        for coins in [0, 1, 7, 1_000_000].iter().copied() {
+            let amount = money_from_coins(coins);
+            assert_eq!(amount.as_nano(), coins * NANOS_PER_COIN);
+            assert_eq!(coins_of(amount), (coins, 0));
+        }
+        let amount = Money::from_nano(3 * NANOS_PER_COIN + 42);
+        assert_eq!(coins_of(amount), (3, 42));
+        assert_eq!(money_from_coins(u64::MAX), Money::from_nano(u64::MAX));
+    }
+
+    #[test]
+    fn even_strategies_pick_the_middle_values() -> Result<()> {
+        let counters = vec![
+            counter(40, 1),
+            counter(10, 9),
+            counter(30, 3),
+            counter(20, 6),
+        ];
+        let set = RewardCounterSet::new(5, counters)?;
+
+        assert_eq!(
+            set.agreed_value_even_strategy(EvenStrategy::Average),
+            Some(counter(25, 4))
+        );
+        assert_eq!(
+            set.agreed_value_even_strategy(EvenStrategy::Lower),
+            Some(counter(20, 3))
+        );
+        assert_eq!(
+            set.agreed_value_even_strategy(EvenStrategy::Upper),
+            Some(counter(30, 6))
+        );
+        let below_quorum = RewardCounterSet::new(5, vec![counter(10, 1)])?;
+        assert_eq!(
+            below_quorum.agreed_value_even_strategy(EvenStrategy::Lower),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_drift_is_relative_to_expected() {
+        let nanos = Money::from_nano;
+        assert_eq!(aggregate_drift(nanos(1_000), nanos(1_000)), 0.0);
+        assert!((aggregate_drift(nanos(1_000), nanos(1_005)) - 0.5).abs() < 1e-9);
+        assert!((aggregate_drift(nanos(1_000), nanos(995)) - 0.5).abs() < 1e-9);
+        assert_eq!(aggregate_drift(Money::zero(), Money::zero()), 0.0);
+        assert_eq!(aggregate_drift(Money::zero(), nanos(1)), 100.0);
+    }
+
+    #[test]
+    fn agreed_value_of_even_length_set_is_median() -> Result<()> {
+        let pair = RewardCounterSet::new(3, vec![counter(10, 2), counter(20, 4)])?;
+        let four = RewardCounterSet::new(
+            5,
+            vec![
+                counter(40, 1),
+                counter(10, 9),
+                counter(30, 3),
+                counter(20, 6),
+            ],
+        )?;
+
+        assert_eq!(pair.agreed_value(), Some(counter(15, 3)));
+        assert_eq!(four.agreed_value(), Some(counter(25, 4)));
+        Ok(())
+    }
+
+    #[test]
+    fn median_of_huge_middle_values_does_not_overflow() -> Result<()> {
+        let max = u64::MAX;
+        let counters = vec![
+            counter(1, 1),
+            counter(max - 3, max - 3),
+            counter(max - 1, max - 1),
+            counter(max, max),
+        ];
+        let set = RewardCounterSet::new(5, counters)?;
+
+        assert_eq!(set.agreed_value(), Some(counter(max - 2, max - 2)));
+        Ok(())
+    }
+
+    #[test]
+    fn reward_cv_is_high_when_reporters_disagree() -> Result<()> {
+        let honest = vec![
+            counter(1_000, 1),
+            counter(1_001, 1),
+            counter(999, 1),
+            counter(1_000, 1),
+            counter(1_002, 1),
+        ];
+        let mut byzantine = honest[..3].to_vec();
+        byzantine.push(counter(u64::MAX / 2, 1));
+        byzantine.push(counter(0, 1));
+        let tight = RewardCounterSet::new(5, honest)?;
+        let wild = RewardCounterSet::new(5, byzantine)?;
+
+        assert!(tight.reward_cv().unwrap() < 0.01);
+        assert!(wild.reward_cv().unwrap() > 1.0);
+        assert_eq!(RewardCounterSet::new(5, vec![])?.reward_cv(), None);
+        let zeros = RewardCounterSet::new(3, vec![counter(0, 1), counter(0, 2)])?;
+        assert_eq!(zeros.reward_cv(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn trimmed_mean_drops_symmetric_outliers() -> Result<()> {
+        let counters = vec![
+            counter(0, 1),
+            counter(100, 10),
+            counter(100, 10),
+            counter(101, 11),
+            counter(104, 14),
+            counter(105, 15),
+            counter(1_000, u64::MAX),
+        ];
+        let set = RewardCounterSet::new(7, counters)?;
+
+        // The median is one of the honest values,
+        // where the trimmed mean is the mean of all of them.
+        assert_eq!(set.agreed_value(), Some(counter(101, 11)));
+        assert_eq!(set.agreed_value_trimmed(1), Some(counter(102, 12)));
+        // Not trimming keeps the outliers.
+        assert_eq!(
+            set.agreed_value_trimmed(0).map(|c| c.reward),
+            Some(Money::from_nano(1_510 / 7))
+        );
+        // Too few remain for the quorum of 4.
+        assert_eq!(set.agreed_value_trimmed(2), None);
+        assert_eq!(set.agreed_value_trimmed(4), None);
+        Ok(())
+    }
+
+    #[test]
+    fn weighted_median_follows_the_trusted_reporter() -> Result<()> {
+        let counters = vec![
+            counter(10, 1),
+            counter(20, 2),
+            counter(30, 3),
+            counter(100, 10),
+            counter(110, 11),
+        ];
+        let set = RewardCounterSet::new(5, counters)?;
+
+        assert_eq!(set.agreed_value(), Some(counter(30, 3)));
+        assert_eq!(
+            set.agreed_value_weighted(&[1, 1, 1, 10, 1], 14),
+            Some(counter(100, 10))
+        );
+        // Equal weights give the plain median.
+        assert_eq!(
+            set.agreed_value_weighted(&[1, 1, 1, 1, 1], 5),
+            set.agreed_value()
+        );
+        assert_eq!(set.agreed_value_weighted(&[1, 1, 1, 1], 4), None);
+        assert_eq!(set.agreed_value_weighted(&[0, 0, 0, 0, 0], 0), None);
+        Ok(())
+    }
+
+    #[test]
+    fn weighted_agreement_needs_a_weight_quorum() -> Result<()> {
+        // Three of five reporters, but the two that have not reported carry most weight.
+        let set = RewardCounterSet::new(5, vec![counter(10, 1), counter(20, 2), counter(30, 3)])?;
+
+        assert_eq!(set.agreed_value_weighted(&[1, 1, 1], 9), None);
+        // Exactly 2/3 is not enough ..
+        assert_eq!(set.agreed_value_weighted(&[2, 2, 2], 9), None);
+        // .. while more than that is, however few the reporters.
+        assert_eq!(
+            set.agreed_value_weighted(&[1, 5, 1], 10),
+            Some(counter(20, 2))
+        );
+        let single = RewardCounterSet::new(3, vec![counter(40, 4)])?;
+        assert_eq!(single.agreed_value_weighted(&[7], 10), Some(counter(40, 4)));
+        Ok(())
+    }
+
+    #[test]
+    fn no_more_than_the_expected_counters_can_be_added() -> Result<()> {
+        let mut set = RewardCounterSet::new(3, vec![])?;
+        for i in 0..3 {
+            set.add(counter(10, i))?;
+        }
+
+        assert_eq!(set.add(counter(10, 3)), Err(FarmingError::TooManyCounters));
+        assert_eq!(set.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn dispersion_is_zero_only_when_reporters_agree() -> Result<()> {
+        let same = RewardCounterSet::new(3, vec![counter(10, 2); 3])?;
+        let spread = RewardCounterSet::new(3, vec![counter(8, 1), counter(10, 2), counter(12, 6)])?;
+
+        let dispersion = same.dispersion().unwrap();
+        assert_eq!(dispersion.mean_reward, 10.0);
+        assert_eq!(dispersion.mean_work, 2.0);
+        assert_eq!(dispersion.reward_variance, 0.0);
+        assert_eq!(dispersion.work_variance, 0.0);
+
+        let dispersion = spread.dispersion().unwrap();
+        assert_eq!(dispersion.mean_reward, 10.0);
+        assert_eq!(dispersion.mean_work, 3.0);
+        assert!(dispersion.reward_variance > 0.0);
+        assert!(dispersion.work_variance > dispersion.reward_variance);
+        assert_eq!(RewardCounterSet::new(3, vec![])?.dispersion(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn agreed_value_below_quorum_tells_the_quorum() -> Result<()> {
+        let set = RewardCounterSet::new(5, vec![counter(10, 1)])?;
+
+        assert_eq!(
+            set.try_agreed_value(),
+            Err(FarmingError::QuorumNotMet {
+                counters: 1,
+                quorum: 2
+            })
+        );
+        assert_eq!(
+            RewardCounterSet::new(4, vec![]).err(),
+            Some(FarmingError::InvalidExpectedCounters(4))
+        );
+        Ok(())
+    }
 }